@@ -0,0 +1,58 @@
+// Items behind a #[cfg] that won't be compiled are left out of the running
+
+use overrider::*;
+
+#[default]
+fn speed() -> &'static str {
+    "default speed"
+}
+
+#[override_default]
+fn speed() -> &'static str {
+    "overriden speed"
+}
+
+// Would win, but only when built with `--features fast`
+#[cfg(feature = "fast")]
+#[override_default(priority = 2)]
+fn speed() -> &'static str {
+    "fast speed"
+}
+
+// The same, without needing a seperate #[cfg]
+#[default]
+fn buffer_size() -> usize {
+    1024
+}
+
+#[override_default(feature = "fast", priority = 3)]
+fn buffer_size() -> usize {
+    65536
+}
+
+#[default]
+fn platform() -> &'static str {
+    "some platform"
+}
+
+// Only competes on the targets matching the predicate
+#[override_cfg(unix)]
+fn platform() -> &'static str {
+    "a unix platform"
+}
+
+#[override_cfg(target_os = "linux", priority = 2)]
+fn platform() -> &'static str {
+    "linux"
+}
+
+#[override_cfg(any(windows, target_os = "macos"), priority = 2)]
+fn platform() -> &'static str {
+    "a desktop platform"
+}
+
+fn main() {
+    println!("{}", speed());
+    println!("Buffering {} bytes", buffer_size());
+    println!("Running on {}", platform());
+}
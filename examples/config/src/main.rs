@@ -0,0 +1,54 @@
+use overrider::*;
+use clap::{ArgMatches, App};
+use overrider_runtime::ConfigFlags;
+
+include!(concat!(env!("OUT_DIR"), "/overrider_flags.rs"));
+
+lazy_static::lazy_static! {
+    // The command line comes first, so it wins over the file named by APP_OVERRIDES
+    static ref FLAGS: (ArgMatches<'static>, ConfigFlags) = (
+	App::new("Overrider example - config")
+            .version(env!("CARGO_PKG_VERSION"))
+	    .args(&OVERRIDER_FLAGS.iter().map(|flag| flag.clap2()).collect::<Vec<_>>())
+            .get_matches(),
+	ConfigFlags::from_env("APP_OVERRIDES").unwrap_or_else(|error| {
+	    eprintln!("{}", error);
+	    std::process::exit(1);
+	}),
+    );
+}
+
+overrider_runtime::flag_source!(&*FLAGS);
+
+/// Run in the default mode
+#[default]
+fn run() {
+    println!("Default mode");
+}
+
+/// Run fast
+#[override_flag(flag = mode, value = "fast")]
+fn run() {
+    println!("Fast mode");
+}
+
+#[override_flag(flag = mode, value = "safe")]
+fn run() {
+    println!("Safe mode");
+}
+
+#[default]
+fn greet() {
+    println!("Hello");
+}
+
+/// Greet the old way
+#[override_flag(flag = legacy)]
+fn greet() {
+    println!("Greetings");
+}
+
+fn main() {
+    greet();
+    run();
+}
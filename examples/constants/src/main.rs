@@ -0,0 +1,20 @@
+// Constants and statics outside of impl blocks can be overriden too
+
+use overrider::*;
+
+#[default]
+const BUFSIZE: usize = 1024;
+
+#[override_default]
+const BUFSIZE: usize = 4096;
+
+#[default]
+static NAME: &str = "default static";
+
+#[override_default(priority = 3)]
+static NAME: &str = "overriden static";
+
+fn main() {
+    println!("buffers are {} bytes", BUFSIZE);
+    println!("{}", NAME);
+}
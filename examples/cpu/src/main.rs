@@ -0,0 +1,55 @@
+// Picking an implementation by what the CPU running the program supports
+
+use overrider::*;
+
+#[default]
+fn sum(values: &[f32]) -> f32 {
+    values.iter().sum()
+}
+
+// The same code, but compiled with AVX enabled, so the compiler can vectorize it
+#[override_cpu(feature = "avx")]
+fn sum(values: &[f32]) -> f32 {
+    values.iter().sum()
+}
+
+#[default]
+fn describe() -> &'static str {
+    "scalar"
+}
+
+// The highest priority supported feature wins
+#[override_cpu(feature = "avx2", priority = 2)]
+fn describe() -> &'static str {
+    "avx2"
+}
+
+#[override_cpu(feature = "sse2", priority = 1)]
+fn describe() -> &'static str {
+    "sse2"
+}
+
+// Trait impls can depend on the CPU too, even for a type from another crate
+trait Checksum {
+    fn checksum(&self) -> u32;
+}
+
+#[default]
+impl Checksum for Vec<u8> {
+    fn checksum(&self) -> u32 {
+	self.iter().map(|&byte| byte as u32).sum()
+    }
+}
+
+#[override_cpu(feature = "avx2")]
+impl Checksum for Vec<u8> {
+    fn checksum(&self) -> u32 {
+	self.iter().map(|&byte| byte as u32).sum()
+    }
+}
+
+fn main() {
+    let values = (1..=100).map(|v| v as f32).collect::<Vec<_>>();
+    println!("sum is {} using {}", sum(&values), describe());
+    println!("checksum is {}", (0..=255).collect::<Vec<u8>>().checksum());
+}
@@ -0,0 +1,29 @@
+// Switching implementations with environment variables, no flag parsing required
+
+use overrider::*;
+
+#[default]
+fn allocator() -> &'static str {
+    "system allocator"
+}
+
+#[override_env(var = "APP_ALLOCATOR", value = "jemalloc")]
+fn allocator() -> &'static str {
+    "jemalloc"
+}
+
+#[override_env(var = "APP_ALLOCATOR", value = "mimalloc")]
+fn allocator() -> &'static str {
+    "mimalloc"
+}
+
+#[default]
+static LOG_LEVEL: &str = "warn";
+
+// Any value will do
+#[override_env(var = "APP_DEBUG", set = true)]
+static LOG_LEVEL: &str = "debug";
+
+fn main() {
+    println!("Using the {}, logging at {}", allocator(), *LOG_LEVEL);
+}
@@ -0,0 +1,3 @@
+fn main() {
+    overrider_build::watch_files(vec!["src/main.rs"]);
+}
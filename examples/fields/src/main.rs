@@ -0,0 +1,60 @@
+use overrider::*;
+use clap::Parser;
+
+/// The command line, as typed fields
+#[derive(Parser)]
+#[command(name = "Overrider example - fields", version)]
+struct Cli {
+    #[arg(long)]
+    verbose: bool,
+    #[arg(long)]
+    quiet: bool,
+    #[arg(long, value_enum, default_value = "safe")]
+    mode: Mode,
+}
+
+#[derive(clap::ValueEnum, Clone)]
+enum Mode {
+    Safe,
+    Fast,
+}
+
+lazy_static::lazy_static! {
+    static ref CLI: Cli = Cli::parse();
+}
+
+overrider_runtime::flag_parser!(Cli, &*CLI);
+
+#[default]
+fn log() {
+    println!("Pass --verbose for more");
+}
+
+#[override_flag(field = verbose)]
+fn log() {
+    println!("Verbose on, read straight from Cli::verbose");
+}
+
+#[default]
+fn greet() {}
+
+#[override_flag(field = quiet, invert = true)]
+fn greet() {
+    println!("Hello! Pass --quiet to skip this");
+}
+
+#[default]
+fn run() {
+    println!("Default mode");
+}
+
+#[override_flag(field = mode, value = "Mode::Fast")]
+fn run() {
+    println!("Fast mode, from --mode fast");
+}
+
+fn main() {
+    greet();
+    log();
+    run();
+}
@@ -1,34 +1,43 @@
 use overrider::*;
-use clap::{Arg, ArgMatches, App};
+use clap::{ArgMatches, App};
 
-// clap parsing is up to you
+// overrider_build lists every flag used below, with help from their doc comments
+include!(concat!(env!("OUT_DIR"), "/overrider_flags.rs"));
+
+// clap parsing is up to you, but the flags can come ready made
 lazy_static::lazy_static! {
     static ref CLAP_FLAGS: ArgMatches<'static> = {
 	App::new("Overrider example - flag")
             .version(env!("CARGO_PKG_VERSION"))
             .about("An example showing overriding based on command line args")
-            .arg(Arg::with_name("a")
-                 .short("a")
-		 .help("A switch to change the output of foo (try it)")
-		 .conflicts_with("b")) // NOTE: defining both is currently undefined behavior
-            .arg(Arg::with_name("b")
-                 .short("b")
-		 .help("Another switch to change the output of foo (try it)"))
+            .args(&OVERRIDER_FLAGS.iter().map(|flag| flag.clap2()).collect::<Vec<_>>())
             .get_matches()
     };
 }
 
+// flagged items read from here
+overrider_runtime::flag_source!(&*CLAP_FLAGS);
+
 // Must provide a default case
 #[default]
 fn foo() {
     println!("Default fn");
 }
 
-#[override_flag(flag = a)]
+// When more than one flag matters, `when` takes a whole expression.
+// The highest priority that applies wins, so this comes before -a or -b alone
+#[override_flag(when = "a && b", priority = 2)]
+fn foo() {
+    println!("fn   changed by both flags");
+}
+
+/// A switch to change the output of foo (try it)
+#[override_flag(flag = a, priority = 1)]
 fn foo() {
     println!("fn   changed by a flag");
 }
 
+/// Another switch to change the output of foo (try it)
 #[override_flag(flag = b)]
 fn foo() {
     println!("fn   changed by a different flag");
@@ -65,10 +74,93 @@ impl Dummy {
     }
 }
 
+// and so is the syntax for trait impls
+#[default]
+impl std::fmt::Display for Dummy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	write!(f, "Default trait impl")
+    }
+}
 
+#[override_flag(flag = b)]
+impl std::fmt::Display for Dummy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	write!(f, "trait impl changed by flag")
+    }
+}
+
+// Statics can be flagged as well. The value is picked the first time it's used
+#[default]
+static GREETING: &str = "Default static";
+
+#[override_flag(flag = a)]
+static GREETING: &str = "static changed by a flag";
+
+// Flags can also be matched by the value they hold
+#[default]
+fn run() {
+    println!("Running in the default mode");
+}
+
+/// Either fast or safe, to change the output of run (try it)
+#[override_flag(flag = mode, value = "fast")]
+fn run() {
+    println!("Running fast");
+}
+
+#[override_flag(flag = mode, value = "safe")]
+fn run() {
+    println!("Running safely");
+}
+
+// Or by how many times they're given, the highest count met winning
+#[default]
+fn log() {
+    println!("Quiet");
+}
+
+/// Give once or more to change the output of log (try it)
+#[override_flag(flag = v)]
+fn log() {
+    println!("Verbose");
+}
+
+#[override_flag(flag = v, min_occurrences = 2)]
+fn log() {
+    println!("Very verbose");
+}
+
+// Flag names that aren't identifiers can be written as strings
+#[default]
+fn save() {
+    println!("Saved");
+}
+
+/// To change the output of save (try it)
+#[override_flag(flag = "dry-run")]
+fn save() {
+    println!("Would have saved");
+}
+
+// Arguments are passed along as they are, whatever they're named
+#[default]
+fn pick(choice: usize) -> usize {
+    choice
+}
+
+#[override_flag(flag = a)]
+fn pick(choice: usize) -> usize {
+    choice * 10
+}
 
 fn main() {
     foo();
+    run();
+    log();
+    save();
     let dummy = Dummy{};
     dummy.foo();
+    println!("{}", dummy);
+    println!("{}", *GREETING);
+    println!("picked {}", pick(7));
 }
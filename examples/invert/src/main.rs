@@ -1,6 +1,8 @@
 use overrider::*;
 use clap::{Arg, ArgMatches, App};
 
+include!(concat!(env!("OUT_DIR"), "/overrider_flags.rs"));
+
 lazy_static::lazy_static! {
     static ref CLAP_FLAGS: ArgMatches<'static> = {
 	App::new("Overrider example - flag")
@@ -11,6 +13,8 @@ lazy_static::lazy_static! {
     };
 }
 
+overrider_runtime::flag_source!(&*CLAP_FLAGS);
+
 #[default]
 fn foo() {
     println!("This is the old default");
@@ -22,5 +26,10 @@ fn foo() {
 }
 
 fn main() {
+    // Catch a misspelled flag before anything reads it
+    if let Err(unknown) = overrider_runtime::check_flags(OVERRIDER_FLAGS, ["disable"]) {
+	eprint!("{}", unknown);
+	std::process::exit(1);
+    }
     foo();
 }
@@ -0,0 +1,59 @@
+// Entire modules can be overriden, bringing everything inside along with them
+
+use overrider::*;
+
+#[default]
+mod backend {
+    pub struct Connection {
+	address: &'static str,
+    }
+
+    impl Connection {
+	pub fn describe(&self) -> String {
+	    self.address.to_string()
+	}
+    }
+
+    pub fn connect() -> Connection {
+	Connection{ address: "localhost" }
+    }
+
+    pub fn name() -> &'static str {
+	"default backend"
+    }
+}
+
+// Everything in here needs to line up with the default, but nothing
+// inside needs an attribute of its own
+#[override_default]
+mod backend {
+    pub struct Connection {
+	address: &'static str,
+	port: u16,
+    }
+
+    impl Connection {
+	pub fn describe(&self) -> String {
+	    format!("{}:{}", self.address, self.port)
+	}
+    }
+
+    pub fn connect() -> Connection {
+	Connection{ address: "remote", port: 8080 }
+    }
+
+    pub fn name() -> &'static str {
+	"overriden backend"
+    }
+}
+
+// Attributes may share a line with the module's braces, too
+#[default] mod units { pub const NAME: &str = "metric"; }
+
+#[override_default] mod units { pub const NAME: &str = "imperial"; }
+
+fn main() {
+    let connection = backend::connect();
+    println!("{} connected to {}", backend::name(), connection.describe());
+    println!("{} units", units::NAME);
+}
@@ -0,0 +1,4 @@
+fn main() {
+    // finds src/storage.rs through the `mod storage;` in src/main.rs
+    overrider_build::watch_crate();
+}
@@ -0,0 +1,14 @@
+// The library is a crate of its own, apart from the binary in main.rs,
+// so their items are overriden separately even where they share a name
+
+use overrider::*;
+
+#[default]
+pub fn helper() -> &'static str {
+    "default library helper"
+}
+
+#[override_default]
+pub fn helper() -> &'static str {
+    "overriden library helper"
+}
@@ -0,0 +1,96 @@
+// Items with the same name in different modules don't get in each other's way
+
+use overrider::*;
+
+mod storage;
+
+mod net {
+    use overrider::*;
+
+    #[default]
+    pub fn init() -> &'static str {
+	"default net"
+    }
+
+    #[override_default]
+    pub fn init() -> &'static str {
+	"overriden net"
+    }
+}
+
+mod db {
+    use overrider::*;
+
+    #[default]
+    pub fn init() -> &'static str {
+	"default db"
+    }
+
+    pub struct Conn;
+
+    #[default]
+    impl Conn {
+	pub fn open() -> &'static str {
+	    "default connection"
+	}
+    }
+
+    #[override_default]
+    impl Conn {
+	pub fn open() -> &'static str {
+	    "overriden connection"
+	}
+    }
+}
+
+#[default]
+fn init() -> &'static str {
+    "default root"
+}
+
+// Items inside a function body are scoped to it, so they can be overriden too
+fn greeting() -> &'static str {
+    #[default]
+    fn word() -> &'static str {
+	"default greeting"
+    }
+
+    #[override_default]
+    fn word() -> &'static str {
+	"overriden greeting"
+    }
+
+    word()
+}
+
+// The function's own attribute may share a line with its body
+#[default] fn farewell() -> &'static str {
+    #[default]
+    fn word() -> &'static str {
+	"default farewell"
+    }
+
+    #[override_default]
+    fn word() -> &'static str {
+	"overriden farewell"
+    }
+
+    word()
+}
+
+#[default]
+fn helper() -> &'static str {
+    "default binary helper"
+}
+
+fn main() {
+    println!("{}", greeting());
+    println!("{}", farewell());
+    println!("{}", init());
+    println!("{}", net::init());
+    println!("{}", db::init());
+    println!("{}", db::Conn::open());
+    println!("{}", storage::init());
+    println!("{}", helper());
+    println!("{}", namespaces::helper());
+}
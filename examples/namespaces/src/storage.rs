@@ -0,0 +1,13 @@
+// Known to be `mod storage` through the `mod storage;` in main.rs
+
+use overrider::*;
+
+#[default]
+pub fn init() -> &'static str {
+    "default storage"
+}
+
+#[override_default(priority = 2)]
+pub fn init() -> &'static str {
+    "overriden storage"
+}
@@ -0,0 +1,114 @@
+// Trait impls work like inherent impls, keyed by both the trait and the type
+
+use overrider::*;
+use std::fmt;
+
+struct Dummy {}
+
+// The #[default] block is the one the trait impl itself comes from,
+// so it must be complete (associated types included)
+#[default]
+impl fmt::Display for Dummy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "default display")
+    }
+}
+
+// Same type, different trait. This doesn't collide with Display
+#[default]
+impl fmt::Debug for Dummy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "default debug")
+    }
+}
+
+#[override_default]
+impl fmt::Display for Dummy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+	write!(f, "overriden display")
+    }
+}
+
+
+struct Counter {
+    count: u32,
+}
+
+#[default]
+impl Iterator for Counter {
+    type Item = u32;
+    fn next(&mut self) -> Option<Self::Item> {
+	self.count += 1;
+	Some(self.count)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+	(0, None)
+    }
+}
+
+// Partial overriding works here too
+#[override_default]
+impl Iterator for Counter {
+    fn next(&mut self) -> Option<Self::Item> {
+	self.count += 2;
+	Some(self.count)
+    }
+}
+
+
+trait Describe {
+    fn describe(&self) -> String;
+}
+
+// Traits can be overriden for types from elsewhere too, even primitives
+#[default]
+impl Describe for u32 {
+    fn describe(&self) -> String {
+	format!("default description of {}", self)
+    }
+}
+
+#[override_default]
+impl Describe for u32 {
+    fn describe(&self) -> String {
+	format!("overriden description of {}", self)
+    }
+}
+
+
+struct Pair(u8, u8);
+
+// Arguments may be patterns rather than names
+#[default]
+impl From<(u8, u8)> for Pair {
+    fn from((first, second): (u8, u8)) -> Self {
+	Pair(first, second)
+    }
+}
+
+#[override_default]
+impl From<(u8, u8)> for Pair {
+    fn from((first, second): (u8, u8)) -> Self {
+	Pair(second, first)
+    }
+}
+
+#[default]
+impl From<u8> for Pair {
+    fn from(_: u8) -> Self {
+	Pair(0, 0)
+    }
+}
+
+fn main() {
+    let dummy = Dummy{};
+    println!("{}", dummy);
+    println!("{:?}", dummy);
+    let counter = Counter{ count: 0 };
+    println!("{:?}", counter.take(3).collect::<Vec<_>>());
+    println!("{}", 7u32.describe());
+    let Pair(first, second) = Pair::from((1, 2));
+    println!("({}, {})", first, second);
+    let Pair(first, second) = Pair::from(5);
+    println!("({}, {})", first, second);
+}
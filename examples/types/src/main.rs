@@ -0,0 +1,80 @@
+// Whole structs and enums can be swapped out at build time
+
+use overrider::*;
+
+#[default]
+struct Buffer {
+    data: Vec<u8>,
+}
+
+// A different layout altogether. Comment this out to see the output change
+#[override_default]
+struct Buffer {
+    data: [u8; 4],
+    len: usize,
+}
+
+// Impls go along with whichever definition is used
+#[default]
+impl Buffer {
+    fn new() -> Self {
+	Buffer{ data: Vec::new() }
+    }
+    fn describe(&self) -> String {
+	format!("growable buffer holding {} bytes", self.data.len())
+    }
+}
+
+#[override_default]
+impl Buffer {
+    fn new() -> Self {
+	Buffer{ data: [0; 4], len: 0 }
+    }
+    fn describe(&self) -> String {
+	format!("fixed buffer holding {} of {} bytes", self.len, self.data.len())
+    }
+}
+
+// Derives come along too, so long as they are placed after the override attribute
+#[default]
+#[derive(Debug)]
+enum Mode {
+    Slow,
+}
+
+#[override_default]
+#[derive(Debug)]
+enum Mode {
+    Slow,
+    Fast,
+}
+
+#[default]
+impl Mode {
+    const CURRENT: Mode = Mode::Slow;
+}
+
+#[override_default]
+impl Mode {
+    const CURRENT: Mode = Mode::Fast;
+}
+
+// Type aliases and imports can swap out which type every call site sees
+#[default]
+type Map = std::collections::BTreeMap<&'static str, u32>;
+
+// Comment this out to go back to a BTreeMap
+#[override_default]
+use backend::Map;
+
+mod backend {
+    pub type Map = std::collections::HashMap<&'static str, u32>;
+}
+
+fn main() {
+    let mut map = Map::default();
+    map.insert("answer", 42);
+    println!("{:?} in a {}", map, std::any::type_name_of_val(&map));
+    println!("{}", Buffer::new().describe());
+    println!("{:?} (could have been {:?})", Mode::CURRENT, Mode::Slow);
+}
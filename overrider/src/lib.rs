@@ -6,14 +6,14 @@
 //! method, or other item can be defined and then later overriden. All of this happens at
 //! compilation time.  
 //! 
-//! `overrider` also allows for defining `flags`. By parsing flags with `clap` in a
-//! `lazy_static`, highly efficient switching of functionality due to input flags
-//! can be achieved.
+//! `overrider` also allows for defining `flags`. By reading flags from `clap` (or
+//! anywhere else) through `overrider_runtime`, highly efficient switching of
+//! functionality due to input flags can be achieved.
 //! 
 //! 
 //! ## Quick Example
 //! The following code shows how `overrider` is used in `src` file:
-//! ```
+//! ```ignore
 //! use overrider::*;
 //! 
 //! #[default]
@@ -28,19 +28,24 @@
 //! ```
 //! Easy as that. If the second implimentation is included, the output changes.  
 //! 
-//! How about with flags?
-//! ```
+//! How about with flags? These are read from a
+//! [`FlagSource`](https://docs.rs/overrider_runtime), registered once per crate with
+//! `overrider_runtime::flag_source!`. Here, `clap` (with `overrider_runtime`'s `clap2`
+//! feature) provides them:
+//! ```ignore
 //! use overrider::*;
 //! use clap::{Arg, ArgMatches, App};
 //! use lazy_static::lazy_static;
 //! 
 //! lazy_static! {
 //!     static ref CLAP_FLAGS: ArgMatches<'static> = {
-//! 	App::new("Overrider example - flag")
+//!         App::new("Overrider example - flag")
 //!             .arg(Arg::with_name("change").long("change"))
 //!             .get_matches()
 //!     };
 //! }
+//!
+//! overrider_runtime::flag_source!(&*CLAP_FLAGS);
 //! 
 //! #[default]
 //! fn main() {
@@ -55,18 +60,21 @@
 //!
 //! Flags can also be inverted if you want it to override the basic functionality,
 //! but be disabled on a flag input. For example
-//! ```use overrider::*;
+//! ```ignore
+//! use overrider::*;
 //! use clap::{Arg, ArgMatches, App};
 //! 
 //! lazy_static::lazy_static! {
 //!     static ref CLAP_FLAGS: ArgMatches<'static> = {
-//! 	App::new("Overrider example - flag")
+//!         App::new("Overrider example - flag")
 //!             .version(env!("CARGO_PKG_VERSION"))
 //!             .arg(Arg::with_name("disable")
 //!                  .long("disable"))
 //!             .get_matches()
 //!     };
 //! }
+//!
+//! overrider_runtime::flag_source!(&*CLAP_FLAGS);
 //! 
 //! #[default]
 //! fn main() {
@@ -76,10 +84,29 @@
 //! #[override_flag(flag = disable, invert = true)]
 //! fn main() {
 //!     println!("This is the new default, pass --disable to turn off");
-//! }```
-//! ### CAUTION
-//! The invert flag causes undefined behavior when multiple override points for the same
-//! item exists. 
+//! }
+//! ```
+//! ### Precedence
+//! When more than one flag of an item is set, the version with the highest priority wins,
+//! whichever flags they are. Two versions for different flags can't share a priority, as
+//! there would be no telling which should win, so this stops the build. Versions that
+//! can never both apply, such as two values of the same flag, are the exception.
+//!
+//! ### Dispatch
+//! Flags are looked at the first time a flagged item is used, and the choice is kept from
+//! then on. Every later call costs one atomic load and a jump to the chosen version, so
+//! flags changing while the program runs aren't noticed.
+//! For a build where the flags are known ahead of time, `OVERRIDER_BAKE_FLAGS="fast,!legacy"`
+//! settles them while building, leaving only the chosen versions in the binary.
+//!
+//! ### Environment variables
+//! `#[override_env(var = "APP_MODE", value = "fast")]` (or `set = true` for any value)
+//! works like a flag, but is set by an environment variable. No flag source is needed.
+//!
+//! ### CPU features
+//! `#[override_cpu(feature = "avx2")]` works like a flag, but is set when the CPU running
+//! the program supports the feature. The overriding version is compiled with the feature
+//! enabled.
 //! 
 //! ## Why not traits?
 //! Rust has a powerful trait system which allows somewhat similar functionality.
@@ -91,14 +118,14 @@
 //! Due to limitations of `proc_macro`, all `overrider` flags __must__ be attached to
 //! the ouside of an `impl` block, __not__ the inside.  
 //! The following is correct:
-//! ```
+//! ```ignore
 //! #[default]
 //! impl Foo {
 //!     fn bar(){}
 //! }
 //! ```
 //! The following is __not__ correct;
-//! ```
+//! ```ignore
 //! impl Foo {
 //!     #[default]
 //!     fn bar(){}
@@ -108,6 +135,99 @@
 //! be manipulated:
 //! - `fn` (methods)
 //! - `const`ants
+//!
+//! ### Trait impls
+//! Trait impls (`impl Trait for Foo`) are overriden separately from each other and from
+//! inherent impls, so `impl Display for Foo` and `impl Debug for Foo` don't interfere.
+//! Because a trait can only be implimented once, the `#[default]` block is the one
+//! that actually implements the trait. It must therefore be complete, and is the only
+//! block allowed to hold associated types. Other blocks may override any subset of its
+//! methods and constants:
+//! ```ignore
+//! #[default]
+//! impl Iterator for Foo {
+//!     type Item = u32;
+//!     fn next(&mut self) -> Option<u32> { None }
+//! }
+//!
+//! #[override_default]
+//! impl Iterator for Foo {
+//!     fn next(&mut self) -> Option<u32> { Some(0) }
+//! }
+//! ```
+//!
+//! ## Types
+//! Whole `struct` and `enum` definitions can be overriden as well, along with `type`
+//! aliases and `use` imports. These all share a namespace, so one kind may override
+//! another of the same name. This makes swapping out a backend a one-liner:
+//! ```ignore
+//! #[default]
+//! type Map = std::collections::BTreeMap<u32, u32>;
+//!
+//! #[override_default]
+//! use fast::Map;
+//! ```
+//! A `use` importing several names is overriden under each of them, so it is only
+//! compiled in if it wins for all of them. Globs and `_` imports can't be overriden.
+//! Place any `#[derive]` after the `overrider` attribute so that it only applies to the
+//! definition that gets compiled in.
+//!
+//! ## Constants and statics
+//! Free standing `const` and `static` items can be overriden like anything else.
+//! Statics may also be flagged. Since flags are only known at runtime, a flagged
+//! static becomes a handle to the value, which is picked the first time it's
+//! dereferenced:
+//! ```ignore
+//! #[default]
+//! static GREETING: &str = "hello";
+//!
+//! #[override_flag(flag = loud)]
+//! static GREETING: &str = "HELLO";
+//!
+//! fn main() {
+//!     println!("{}", *GREETING);
+//! }
+//! ```
+//! Constants can't be flagged, as their value must be known at compile time.
+//!
+//! ## Modules
+//! An inline `mod` can be overriden as a whole, for when a group of items must be
+//! swapped together. Only the winning module is compiled in, along with all of its
+//! contents:
+//! ```ignore
+//! #[default]
+//! mod backend {
+//!     pub fn connect() {}
+//! }
+//!
+//! #[override_default]
+//! mod backend {
+//!     pub fn connect() { println!("connecting elsewhere") }
+//! }
+//! ```
+//!
+//! Every item is known by the module it's in as well as its name, so overriding
+//! `net::init` leaves `db::init` alone. This goes for modules in files of their own
+//! too, as long as `build.rs` watches the file with the `mod` declaration.
+//! Items inside a function body are scoped to that function in the same way.
+//!
+//! ## Conditional compilation
+//! An override marked with `#[cfg(...)]` only competes when it's going to be compiled,
+//! so the next in line takes over when a feature is off or the target doesn't match:
+//! ```ignore
+//! #[default]
+//! fn checksum() {}
+//!
+//! #[cfg(feature = "simd")]
+//! #[override_default]
+//! fn checksum() { println!("vectorized") }
+//! ```
+//! `#[override_default(feature = "simd")]` is a shorter way of writing the above. For
+//! anything other than features, `#[override_cfg(...)]` takes any `cfg` predicate:
+//! ```ignore
+//! #[override_cfg(target_os = "linux", priority = 2)]
+//! fn checksum() { println!("linux") }
+//! ```
 //! 
 //! 
 //! ## Building
@@ -115,11 +235,13 @@
 //! it's sister crate `overrider_build`. This is because `overrider_build` parses
 //! Rust files, supplying the `rustc` configuration flags nessicary for conditional
 //! compilation. For the above files, placing this code in `build.rs` will do the trick:
-//! ```
+//! ```ignore
 //! fn main() {
 //!     overrider_build::watch_files(vec!["src/main.rs"]);
 //! }
 //! ```
+//! `overrider_build::watch_crate()` can be used instead, to find every file in the
+//! crate by following its `mod` declarations.
 //! For more information, see the `overrider_build` documentation.
 //! 
 //! 
@@ -129,11 +251,14 @@
 //! Try cloning the repository and running examples with `cargo run -p EXAMPLE_NAME`
 
 
-use syn::{parse::Nothing, spanned::Spanned, ImplItem::{Method, Const}, Type::Path,
-	  ItemFn, ItemImpl, DeriveInput, Ident, Attribute};
+use syn::{parse::{Nothing, Parser}, punctuated::Punctuated, spanned::Spanned, ImplItem::{Method, Const},
+	  ItemFn, ItemImpl, Item, ItemStruct, ItemEnum, ItemType, ItemUse, ItemConst, ItemStatic,
+	  ItemMod, DeriveInput, Ident, Attribute, visit_mut::VisitMut};
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::quote;
+use overrider_common::{file_id, impl_name, path_name, use_names, CpuArgs, EnvArgs, FlagArgs,
+		       Version, When};
 
 /// Throw a compiler error to help ensure this item gets compiled
 ///
@@ -142,16 +267,16 @@ use quote::quote;
 /// included in the final compilation.
 ///
 /// ## Syntax
-/// Simply add `#[override_final]` to a `fn` or `impl` block.
+/// Simply add `#[override_final]` to a `fn`, `impl` block, `const`, `static`, `mod`, or
+/// type definition.
 #[proc_macro_attribute]
 pub fn override_final(attr: TokenStream, input: TokenStream)-> TokenStream {
     syn::parse_macro_input!(attr as Nothing); // I take no args
     if let Ok(impl_block) = syn::parse::<ItemImpl>(input.clone()) {
-	let self_type = match impl_block.self_ty.as_ref() { // TODO function
-	    Path(path) => path,
-	    _ => return quick_error(format!("Could not get Path for impl \
-					     (should never see this)")),
-	}.path.segments[0].ident.to_string();
+	let self_type = match impl_name(&impl_block) {
+	    Ok(self_type) => format!("{}{}", module_prefix(), self_type),
+	    Err(error) => return error.to_compile_error().into(),
+	};
 	match impl_block.items.into_iter().fold(None, |acc, item| {
 	    let new_error = match item {
 		Method(method) => {
@@ -164,15 +289,15 @@ pub fn override_final(attr: TokenStream, input: TokenStream)-> TokenStream {
 			method.sig.ident.span(),
 			match priority_lesser.as_str() {
 			    "0" => 
-				format!("Method requested final. \
+				"Method requested final. \
 					 Replace #[override_final] with #[default] or higher \
 					 on a (seperate if required) impl block to make top \
-					 level."),
+					 level.".to_string(),
 			    "1" => 
-				format!("Method requested final. \
+				"Method requested final. \
 					 Replace #[override_final] with #[override_default] \
 					 or higher on a (seperate if required) impl block to \
-					 make top level."),
+					 make top level.".to_string(),
 			    priority_lesser => 
 				format!("Method requested final. \
 					 Replace #[override_final] with \
@@ -192,15 +317,15 @@ pub fn override_final(attr: TokenStream, input: TokenStream)-> TokenStream {
 			constant.ident.span(),
 			match priority_lesser.as_str() {
 			    "0" => 
-				format!("Impl constant requested final. \
+				"Impl constant requested final. \
 					 Replace #[override_final] with #[default] or higher \
 					 on a (seperate if required) impl block to make top \
-					 level."),
+					 level.".to_string(),
 			    "1" => 
-				format!("Impl constant requested final. \
+				"Impl constant requested final. \
 					 Replace #[override_final] with #[override_default] \
 					 or higher on a (seperate if required) impl block \
-					 to make top level."),
+					 to make top level.".to_string(),
 			    priority_lesser => 
 				format!("Impl constant requested final. \
 					 Replace #[override_final] with \
@@ -211,7 +336,7 @@ pub fn override_final(attr: TokenStream, input: TokenStream)-> TokenStream {
 		    )
 		}
 		item => syn::Error::new(item.span(),
-					format!("I can't finalize this yet")),
+					"I can't finalize this yet".to_string()),
 	    };
 	    match acc {
 		None => Some(new_error),
@@ -224,43 +349,58 @@ pub fn override_final(attr: TokenStream, input: TokenStream)-> TokenStream {
 	    Some(errors) => errors.to_compile_error().into(),
 	    None => input, // will only happen if user tries to finalize an empty impl block
 	}
-    } else if let Ok(item) = syn::parse::<ItemFn>(input) {
-	let priority_lesser = 
-	    std::env::var(format!("__override_final_func_{}", &item.sig.ident.to_string()))
-	    .expect("Failed covering final. \
-		     Did you configure your build script to watch this file?");
-	return syn::Error::new(
-	    item.sig.ident.span(),
-	    match priority_lesser.as_str() {
-		"0" => 
-		    format!("Function requested final. \
-			     Replace #[override_final] with #[default] or higher \
-			     to make top level."),
-		"1" => 
-		    format!("Function requested final. \
-			     Replace #[override_final] with #[override_default] \
-			     or higher to make top level."),
-		priority_lesser => 
-		    format!("Function requested final. \
-			     Replace #[override_final] with #[override_default(priority = {})] \
-			     or higher  to make top level.",
-			    priority_lesser),
-	    }
-	).to_compile_error().into();
+    } else if let Ok(item) = syn::parse::<Item>(input) {
+	let (keys, kind) = match item_keys(&item) {
+	    Ok(keys) => keys,
+	    Err(_) => return quick_error("I can't finalize whatever this is attached to yet"
+					 .to_string()),
+	};
+	let errors = keys.into_iter().map(|(ident, key)| {
+	    let priority_lesser = 
+		std::env::var(format!("__override_final_{}", key))
+		.expect("Failed covering final. \
+			 Did you configure your build script to watch this file?");
+	    syn::Error::new(
+		ident.span(),
+		match priority_lesser.as_str() {
+		    "0" => 
+			format!("{} requested final. \
+				 Replace #[override_final] with #[default] or higher \
+				 to make top level.", kind),
+		    "1" => 
+			format!("{} requested final. \
+				 Replace #[override_final] with #[override_default] \
+				 or higher to make top level.", kind),
+		    priority_lesser => 
+			format!("{} requested final. \
+				 Replace #[override_final] with #[override_default(priority = {})] \
+				 or higher  to make top level.",
+				kind, priority_lesser),
+		}
+	    )
+	}).reduce(|mut errors, new_error| {
+	    errors.combine(new_error);
+	    errors
+	});
+	match errors {
+	    Some(errors) => errors.to_compile_error().into(),
+	    None => unreachable!(), // item_keys never comes back empty handed
+	}
     } else {
-	quick_error(format!("I can't finalize whatever this is attached to yet"))
+	quick_error("I can't finalize whatever this is attached to yet".to_string())
     }
 }
 
 /// Marks an item as the base implimentation
 ///
-/// Attaching this attribute to a `fn` or `impl` block enables it to be overriden.
+/// Attaching this attribute to a `fn`, `impl` block, `const`, `static`, `mod`, or type
+/// definition (`struct`, `enum`, `type`, `use`) enables it to be overriden.
 ///
 /// `#[default]` is short hand for `#[override_default(priority = 0)]`
 ///
 /// ### Syntax
 /// Here's an example showing how to flag a function as default:
-/// ```
+/// ```ignore
 /// #[default]
 /// fn main() {
 ///     println!("Default");
@@ -275,19 +415,23 @@ pub fn default(attr: TokenStream, input: TokenStream) -> TokenStream {
 
 /// Replaces (overrides) base implimentation
 ///
-/// Attaching this attribute to `fn` or `impl` block overrides the implimentation
-/// defined with `#[default]`. `overrider` will intelligently determine which implimentation
-/// should be compiled in.
+/// Attaching this attribute to a `fn`, `impl` block, `const`, `static`, `mod`, or type
+/// definition (`struct`, `enum`, `type`, `use`) overrides the implimentation defined with `#[default]`. `overrider` will
+/// intelligently determine which implimentation should be compiled in.
 ///
 /// `#[override_default]` accepts a single, optinal arguement: `priority`. By setting
 /// the priority of a particular implimentation higher, `overrider` will prefer it over
 /// any other implimentation, even other `#[override_default]` implientations, so long
 /// as it holds the highest priority.
 ///
+/// A second optional argument, `feature`, names a Cargo feature of the crate. The
+/// implementation only competes (and is only compiled) when that feature is enabled:
+/// `#[override_default(feature = "simd", priority = 3)]`.
+///
 /// ### Syntax
 /// Here's an example showing how to override a function, and then override it a second
 /// time with a higher priority.
-/// ```
+/// ```ignore
 /// #[override_default]
 /// fn main() {
 ///     println!("I won't run");
@@ -303,33 +447,98 @@ pub fn default(attr: TokenStream, input: TokenStream) -> TokenStream {
 /// [`#[override_final]`](attr.override_default.html)
 #[proc_macro_attribute]
 pub fn override_default(attr: TokenStream, input: TokenStream) -> TokenStream {
-    let priority = {
-	if let Ok(_) = syn::parse::<Nothing>(attr.clone()) {
-	    1
-	} else if let Ok(syn::Expr::Assign(assign)) = syn::parse::<syn::Expr>(attr.clone()) {
-	    if let (syn::Expr::Path(left), syn::Expr::Lit(right)) = (*assign.left, *assign.right) {
-		if left.path.segments[0].ident.to_string() == "priority" {
-		    if let syn::Lit::Int(lit) = right.lit {
-			if let Ok(i) = lit.base10_parse::<u32>() {
-			    i
-			} else {
-			    return quick_error(format!("Could not parse literal"));
-			}                                     
-		    } else {
-			return quick_error(format!("Expected integer literal"));
-		    }
-		} else {
-		    return quick_error(format!("Unexpected arguement name"));
-		}
-	    } else {
-		return quick_error(format!("Incorrect arguement format / expected positive integer"));
-	    }
+    let args = match Punctuated::<syn::MetaNameValue, syn::Token![,]>::parse_terminated
+	.parse(attr) {
+	    Ok(args) => args,
+	    Err(error) => return error.to_compile_error().into(),
+	};
+    let mut priority = 1;
+    let mut feature = None;
+    for arg in args {
+	if arg.path.is_ident("priority") {
+	    priority = match &arg.lit {
+		syn::Lit::Int(lit) => match lit.base10_parse::<u32>() {
+		    Ok(priority) => priority,
+		    Err(error) => return error.to_compile_error().into(),
+		},
+		lit => return syn::Error::new(lit.span(), "Expected positive integer literal")
+		    .to_compile_error().into(),
+	    };
+	} else if arg.path.is_ident("feature") {
+	    feature = match arg.lit {
+		syn::Lit::Str(lit) => Some(lit),
+		lit => return syn::Error::new(lit.span(), "Expected string literal")
+		    .to_compile_error().into(),
+	    };
 	} else {
-	    return quick_error(format!("Unexpected arguement {} (expected priority)", attr.to_string()));
+	    return syn::Error::new(arg.path.span(),
+				   "Unexpected argument (expected priority or feature)")
+		.to_compile_error().into();
 	}
-    };
+    }
 
-    attach(input, priority)
+    match feature {
+	// overrider_build leaves this out of the running too, when the feature is off
+	Some(feature) => {
+	    let input = proc_macro2::TokenStream::from(input);
+	    attach(quote!(#[cfg(feature = #feature)] #input).into(), priority)
+	},
+	None => attach(input, priority),
+    }
+}
+
+/// Replaces (overrides) base implementation, but only for some targets
+///
+/// Works just like [`#[override_default]`](attr.override_default.html), except the
+/// implementation only competes (and is only compiled) when a `cfg` predicate holds.
+/// `overrider_build` checks the predicate against the target being built for, so each
+/// target gets the right chain of priorities.
+///
+/// ### Syntax
+/// Any predicate `#[cfg(...)]` accepts can be given, along with an optional `priority`:
+/// ```ignore
+/// #[default]
+/// fn open() {}
+///
+/// #[override_cfg(target_os = "linux", priority = 2)]
+/// fn open() { println!("using io_uring") }
+///
+/// #[override_cfg(any(windows, target_os = "macos"))]
+/// fn open() { println!("using something else") }
+/// ```
+/// Giving more than one predicate requires all of them to hold. `test`, `doc` and
+/// `doctest` can't be used, as one run of the build script serves builds with and
+/// without them.
+#[proc_macro_attribute]
+pub fn override_cfg(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let args = match Punctuated::<syn::NestedMeta, syn::Token![,]>::parse_terminated
+	.parse(attr) {
+	    Ok(args) => args,
+	    Err(error) => return error.to_compile_error().into(),
+	};
+    let mut priority = 1;
+    let mut predicates = Vec::new();
+    for arg in args {
+	match arg {
+	    syn::NestedMeta::Meta(syn::Meta::NameValue(arg)) if arg.path.is_ident("priority") =>
+		priority = match &arg.lit {
+		    syn::Lit::Int(lit) => match lit.base10_parse::<u32>() {
+			Ok(priority) => priority,
+			Err(error) => return error.to_compile_error().into(),
+		    },
+		    lit => return syn::Error::new(lit.span(), "Expected positive integer literal")
+			.to_compile_error().into(),
+		},
+	    predicate => predicates.push(predicate),
+	}
+    }
+    if predicates.is_empty() {
+	return quick_error("Missing cfg predicate, such as `target_os = \"linux\"`".to_string());
+    }
+
+    // overrider_build leaves this out of the running too, when the predicate is false
+    let input = proc_macro2::TokenStream::from(input);
+    attach(quote!(#[cfg(all(#(#predicates),*))] #input).into(), priority)
 }
 
 fn quick_error(message: String) -> TokenStream {
@@ -339,88 +548,127 @@ fn quick_error(message: String) -> TokenStream {
     ).to_compile_error().into()
 }
 
+/// The module being expanded in, as `foo__bar__` for `foo::bar` (nothing for the crate
+/// root). `overrider_build` notes down which parts of each file belong to which module,
+/// from the line and column of a `{` to those of its `}`
+fn module_prefix() -> String {
+    let span = proc_macro::Span::call_site();
+    let file = span.local_file().map(|file| file.canonicalize().unwrap_or(file));
+    let modules = match file.and_then(|file| std::env::var(format!("__override_modules_{}",
+								   file_id(&file))).ok()) {
+	Some(modules) => modules,
+	None => return String::new(), // nothing but the crate root here
+    };
+    let mut modules = modules.split(';');
+    let base = modules.next().unwrap_or("").to_string();
+    // Inline modules are listed outermost first, so the last match is the innermost
+    // columns start at 1 here, and at 0 for overrider_build
+    let at = (span.line(), span.column() - 1);
+    let position = |position: &str| {
+	let (line, column) = position.split_once(':')?;
+	Some((line.parse::<usize>().ok()?, column.parse::<usize>().ok()?))
+    };
+    modules.rev().find_map(|module| {
+	let (range, prefix) = module.split_once('=')?;
+	let (start, end) = range.split_once('-')?;
+	(position(start)? < at && at < position(end)?).then(|| prefix.to_string())
+    }).unwrap_or(base)
+}
+
 fn attach(input: TokenStream, priority: u32) -> TokenStream { // TODO: do this with traits
     if let Ok(item) = syn::parse::<ItemImpl>(input.clone()) {
 	attach_impl(item, priority)
-    } else if let Ok(item) = syn::parse::<ItemFn>(input) {
+    } else if let Ok(item) = syn::parse::<ItemFn>(input.clone()) {
 	attach_function(item, priority)
+    } else if let Ok(item) = syn::parse::<ItemStatic>(input.clone()) {
+	attach_static(item, priority)
+    } else if let Ok(mut item) = syn::parse::<Item>(input) {
+	let keys = match item_keys(&item) {
+	    Ok((keys, _)) => keys,
+	    Err(error) => return error.to_compile_error().into(),
+	};
+	let attrs = match &mut item {
+	    Item::Struct(ItemStruct{attrs, ..}) | Item::Enum(ItemEnum{attrs, ..})
+		| Item::Type(ItemType{attrs, ..}) | Item::Use(ItemUse{attrs, ..})
+		| Item::Const(ItemConst{attrs, ..}) | Item::Mod(ItemMod{attrs, ..}) => attrs,
+	    _ => return quick_error("I can't parse this yet".to_string()),
+	};
+	for (_, key) in keys {
+	    attr_add(attrs, format!("__override_priority_{}_{}", priority, key));
+	}
+	TokenStream::from(quote! {
+	    #item
+	})
     } else {
-	quick_error(format!("I can't parse this yet"))
+	quick_error("I can't parse this yet".to_string())
     }
 }
 
+/// The names an item is overriden by (as in `__override_priority_N_{name}`),
+/// and what to call it in error messages
+fn item_keys(item: &Item) -> syn::Result<(Vec<(Ident, String)>, &'static str)> {
+    let prefix = module_prefix();
+    Ok(match item {
+	Item::Fn(item) =>
+	    (vec![(item.sig.ident.clone(), format!("func_{}{}", prefix, item.sig.ident))],
+	     "Function"),
+	// These all share the type namespace, so can stand in for one another
+	Item::Struct(ItemStruct{ident, ..}) | Item::Enum(ItemEnum{ident, ..})
+	    | Item::Type(ItemType{ident, ..}) =>
+	    (vec![(ident.clone(), format!("type_{}{}", prefix, ident))], "Type"),
+	Item::Const(item) =>
+	    (vec![(item.ident.clone(), format!("const_{}{}", prefix, item.ident))], "Constant"),
+	Item::Static(item) =>
+	    (vec![(item.ident.clone(), format!("static_{}{}", prefix, item.ident))], "Static"),
+	Item::Mod(item) =>
+	    (vec![(item.ident.clone(), format!("mod_{}{}", prefix, item.ident))], "Module"),
+	Item::Use(item) => {
+	    let mut names = Vec::new();
+	    use_names(&item.tree, None, &mut names);
+	    if names.is_empty() {
+		return Err(syn::Error::new(
+		    item.span(),
+		    "Can only override a `use` that names what it imports (no globs or `_`)"));
+	    }
+	    (names.into_iter().map(|name| {
+		let key = format!("type_{}{}", prefix, name);
+		(name, key)
+	    }).collect(), "Import")
+	},
+	item => return Err(syn::Error::new(item.span(), "I can't parse this yet")),
+    })
+}
+
 fn attach_function(mut input: ItemFn, priority: u32) -> TokenStream {
-    attr_add(&mut input.attrs,
-	     format!("__override_priority_{}_func_{}", priority, &input.sig.ident));
-    match std::env::var(format!("__override_acceptflags_func_{}", &input.sig.ident)) {
+    let key = format!("func_{}{}", module_prefix(), &input.sig.ident);
+    attr_add(&mut input.attrs, format!("__override_priority_{}_{}", priority, key));
+    match std::env::var(format!("__override_acceptflags_{}", key)) {
 	Err(_) => { // no flags to worry about
 	    TokenStream::from(quote! {
 		#input
 	    })
 	},
 	Ok(flagstrs) => {
-	    let flags = flagstrs.split(" ").map(|f| {
-		let v = f.split("_").collect::<Vec<&str>>();
-		(v[0], v[1])
-	    }).collect::<Vec<(&str, &str)>>();
 	    let old_attrs = input.attrs.clone();
 	    let old_ident = &input.sig.ident;
-	    let old_sig = input.sig.clone();
-	    
-	    let mut args = Vec::new();
-	    for input in &input.sig.inputs {
-		match input {
-		    syn::FnArg::Typed(t) => {
-			match t.pat.as_ref() {
-			    syn::Pat::Ident(p) => args.push(&p.ident),
-			    _ => return syn::Error::new(
-				t.span(),
-				format!("I do not know what this is and \
-					 it can't be overriden"))
-				.to_compile_error().into(),
-			}
-		    },
-		    arg => {
-			return syn::Error::new(
-				arg.span(),
-				format!("I can only override typed arguments"))
-			    .to_compile_error().into()
-		    },
-		}
-	    };
+	    let mut old_sig = input.sig.clone();
+	    let (args, _) = sig_args(&mut old_sig);
 	    
-	    let if_branches = flags.into_iter().map(|(modifiers, flagstr)| {
-		let flagext = Ident::new(&format!("__override_flagext_{}_{}",
-						  flagstr, old_ident),
-					 Span::call_site());
-		if modifiers.find("i").is_some() {
-		    quote! {
-			if CLAP_FLAGS.occurrences_of(#flagstr) == 0 {
-			    #flagext (#(#args),*)
-			}
-		    }
-		} else {
-		    quote! {
-			if CLAP_FLAGS.occurrences_of(#flagstr) > 0 {
-			    #flagext (#(#args),*)
-			}
-		    }
-		}
-	    }).collect::<Vec<proc_macro2::TokenStream>>();
-
 	    input.sig.ident = Ident::new(&format!("__override_flagentry_{}",
 						  old_ident),
 					 Span::call_site());
 	    let sigentry = &input.sig.ident;
 
+	    let dispatch = flag_dispatch(&flagstrs, &old_sig.ident, |flagext| quote! {
+		#flagext (#(#args),*)
+	    }, quote!(#sigentry (#(#args),*)));
+
 	    attr_inline(&mut input.attrs);
 	    
 	    TokenStream::from(quote! {
 		#(#old_attrs)*
 		#old_sig {
-		    #(#if_branches else )* {
-			#sigentry (#(#args),*)
-		    }
+		    #dispatch
 		}
 		
 		#input
@@ -429,15 +677,104 @@ fn attach_function(mut input: ItemFn, priority: u32) -> TokenStream {
     }
 }
 
+fn attach_static(mut input: ItemStatic, priority: u32) -> TokenStream {
+    let key = format!("static_{}{}", module_prefix(), &input.ident);
+    attr_add(&mut input.attrs, format!("__override_priority_{}_{}", priority, key));
+    let flagstrs = match std::env::var(format!("__override_acceptflags_{}", key)) {
+	Ok(flagstrs) => Some(flagstrs),
+	Err(_) if std::env::var(format!("__override_baked_{}", key)).is_ok() => None,
+	Err(_) => return TokenStream::from(quote! { // no flags to worry about
+	    #input
+	}),
+    };
+    if let Some(mutability) = input.mutability {
+	return syn::Error::new(mutability.span(), "A flagged static can't be mutable")
+	    .to_compile_error().into();
+    }
+
+    StaticLifetimes.visit_type_mut(&mut input.ty);
+    let ItemStatic{attrs, vis, ident, ty, expr, ..} = &input;
+    let flagstrs = match flagstrs {
+	Some(flagstrs) => flagstrs,
+	// every flag was baked away, but it's still used through a handle
+	None => return static_handle(attrs, vis, ident, ty, quote!(#expr)).into(),
+    };
+    let cfgs = attrs.iter().filter(|attr| attr.path.is_ident("cfg")).collect::<Vec<_>>();
+    let entry = Ident::new(&format!("__override_flagentry_{}", ident), Span::call_site());
+    let dispatch = flag_dispatch(&flagstrs, ident, |flagext| quote!(#flagext()), quote!(#entry()));
+    let handle = static_handle(attrs, vis, ident, ty, dispatch);
+
+    TokenStream::from(quote! {
+	#handle
+
+	#(#cfgs)*
+	#[allow(non_snake_case)]
+	#[inline(always)]
+	fn #entry() -> #ty {
+	    #expr
+	}
+    })
+}
+
+/// Flags are only known at runtime, so a flagged static becomes a handle to a value
+/// picked on first use, by `init`. This works much like `lazy_static`
+fn static_handle(attrs: &[Attribute], vis: &syn::Visibility, ident: &Ident, ty: &syn::Type,
+		 init: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let cfgs = attrs.iter().filter(|attr| attr.path.is_ident("cfg")).collect::<Vec<_>>();
+    let handle = Ident::new(&format!("__override_static_{}", ident), Span::call_site());
+    quote! {
+	#(#attrs)*
+	#vis static #ident: #handle = #handle;
+
+	#(#cfgs)*
+	#[allow(non_camel_case_types)]
+	#[doc(hidden)]
+	#vis struct #handle;
+
+	#(#cfgs)*
+	impl ::std::ops::Deref for #handle {
+	    type Target = #ty;
+	    fn deref(&self) -> &#ty {
+		static VALUE: ::std::sync::OnceLock<#ty> = ::std::sync::OnceLock::new();
+		VALUE.get_or_init(|| {
+		    #init
+		})
+	    }
+	}
+    }
+}
+
+/// Lifetimes may be elided in the type of a static, but nowhere else
+struct StaticLifetimes;
+
+impl VisitMut for StaticLifetimes {
+    fn visit_type_reference_mut(&mut self, reference: &mut syn::TypeReference) {
+	if reference.lifetime.is_none() {
+	    reference.lifetime = Some(syn::parse_quote!('static));
+	}
+	syn::visit_mut::visit_type_reference_mut(self, reference);
+    }
+    fn visit_lifetime_mut(&mut self, lifetime: &mut syn::Lifetime) {
+	if lifetime.ident == "_" {
+	    *lifetime = syn::parse_quote!('static);
+	}
+    }
+}
+
 fn attach_impl(mut input: ItemImpl, priority: u32) -> TokenStream {
     // First, grab the struct name
-    let self_type = match input.self_ty.as_ref() {
-	Path(path) => path,
-	item => return syn::Error::new(
-	    item.span(),
-	    format!("Could not get Path for impl (should never see this)"))
-	    .to_compile_error().into(),
-    }.path.segments[0].ident.to_string();
+    let self_type = match impl_name(&input) {
+	Ok(self_type) => format!("{}{}", module_prefix(), self_type),
+	Err(error) => return error.to_compile_error().into(),
+    };
+    // Trait impls are forwarded to helper trait members, and only #[default] keeps the trait impl
+    let (forward, trait_name, trait_path) = match split_trait_impl(&mut input, priority == 0) {
+	Ok(Some((forward, trait_name, trait_path))) =>
+	    (Some(forward), Some(trait_name), Some(trait_path)),
+	Ok(None) => (None, None, None),
+	Err(error) => return error.to_compile_error().into(),
+    };
+    let forward = forward.filter(|_| priority == 0);
 
     let mut additional_items: Vec::<syn::ImplItem> = Vec::new();
 
@@ -445,75 +782,21 @@ fn attach_impl(mut input: ItemImpl, priority: u32) -> TokenStream {
     for item in &mut input.items {
 	match item {
 	    Method(method) => {
+		let name = method.sig.ident.to_string();
+		if let Some(trait_name) = &trait_name {
+		    method.sig.ident = trait_member(trait_name, &method.sig.ident);
+		}
 		attr_add(&mut method.attrs, format!("__override_priority_{}_method_{}_{}",
 						    priority,
 						    self_type,
-						    &method.sig.ident));
+						    name));
 		if let Ok(flagstrs) = std::env::var(format!("__override_acceptflags_method_{}_{}",
-							    self_type, &method.sig.ident)) {
-		    let flags = flagstrs.split(" ").map(|f| {
-			let v = f.split("_").collect::<Vec<&str>>();
-			(v[0], v[1])
-		    }).collect::<Vec<(&str, &str)>>();
+							    self_type, name)) {
 		    let old_attrs = method.attrs.clone();
 		    let old_ident = &method.sig.ident;
-		    let old_sig = method.sig.clone();
-
-		    let mut args = Vec::new();
-		    let mut receiver = false;
-		    for input in &method.sig.inputs {
-			match input {
-			    syn::FnArg::Typed(t) => {
-				match t.pat.as_ref() {
-				    syn::Pat::Ident(p) => args.push(&p.ident),
-				    _ => return syn::Error::new(
-					t.span(),
-					format!("I do not know what this is and \
-						 it can't be overriden"))
-				.to_compile_error().into(),
-				}
-			    },
-			    syn::FnArg::Receiver(_) => {
-				receiver = true;
-			    },
-			}
-		    };
+		    let mut old_sig = method.sig.clone();
+		    let (args, receiver) = sig_args(&mut old_sig);
 		    
-		    let if_branches = flags.into_iter().map(|(modifiers, flagstr)| {
-			let flagext = Ident::new(&format!("__override_flagext_{}_{}",
-							  flagstr, old_ident),
-						 Span::call_site());
-			if receiver {
-			    if modifiers.find("i").is_some() {
-				quote! {
-				    if CLAP_FLAGS.occurrences_of(#flagstr) == 0 {
-					self.#flagext (#(#args),*)
-				    }
-				}
-			    } else {
-				quote! {
-				    if CLAP_FLAGS.occurrences_of(#flagstr) > 0 {
-					self.#flagext (#(#args),*)
-				    }
-				}
-			    }
-			} else {
-			    if modifiers.find("i").is_some() {
-				quote! {
-				    if CLAP_FLAGS.occurrences_of(#flagstr) == 0 {
-					Self::#flagext (#(#args),*)
-				    }
-				}
-			    } else {
-				quote! {
-				    if CLAP_FLAGS.occurrences_of(#flagstr) > 0 {
-					Self::#flagext (#(#args),*)
-				    }
-				}
-			    }
-			}
-		    }).collect::<Vec<proc_macro2::TokenStream>>();
-
 		    method.sig.ident = Ident::new(&format!("__override_flagentry_{}",
 							   old_ident),
 						  Span::call_site());
@@ -526,6 +809,12 @@ fn attach_impl(mut input: ItemImpl, priority: u32) -> TokenStream {
 		    } else {
 			"Self::".parse::<TokenStream>()
 		    }.unwrap().into();
+
+		    let dispatch = flag_dispatch(&flagstrs, &old_sig.ident, |flagext| if receiver {
+			quote!(self.#flagext (#(#args),*))
+		    } else {
+			quote!(Self::#flagext (#(#args),*))
+		    }, quote!(#self_tok #sigentry (#(#args),*)));
 		    
 		    let vis_tok: proc_macro2::TokenStream = match method.vis {
 			syn::Visibility::Public(_) => "pub".parse::<TokenStream>(),
@@ -535,40 +824,324 @@ fn attach_impl(mut input: ItemImpl, priority: u32) -> TokenStream {
 		    additional_items.push(syn::parse2::<syn::ImplItem>(quote! {
 			#(#old_attrs)*
 			#vis_tok #old_sig {
-			    #(#if_branches else )* {
-				#self_tok #sigentry (#(#args),*)
-			    }
+			    #dispatch
 			}
 		    }).unwrap());
 		}
 	    },
-	    Const(constant) =>
-		match std::env::var(format!("__override_acceptflags_method_{}", self_type)) {
+	    Const(constant) => {
+		let name = constant.ident.to_string();
+		if let Some(trait_name) = &trait_name {
+		    constant.ident = trait_member(trait_name, &constant.ident);
+		}
+		match std::env::var(format!("__override_acceptflags_implconst_{}_{}",
+					    self_type, name)) {
 		    Err(_) => // no flags to worry about
 			attr_add(&mut constant.attrs,
 				 format!("__override_priority_{}_implconst_{}_{}",
 					 priority,
 					 self_type,
-					 &constant.ident)),
+					 name)),
 		    Ok(_) => return syn::Error::new(
-			item.span(),
-			format!("Laying flags on const currently envokes undefined behavior"))
+			constant.span(),
+			"A const must be known at compile time, so can't be flagged. \
+			 Try a static instead")
 			.to_compile_error().into(),
-		},
+		}
+	    },
 	    item => return syn::Error::new(
 		item.span(),
-		format!("I can't overload anything other than methods/consts \
-			 in an impl block yet"))
+		"I can't overload anything other than methods/consts \
+		 in an impl block yet")
 		.to_compile_error().into(),
 	}
     }
     input.items.append(&mut additional_items);
+    let helper = trait_path.map(|trait_path| helper_trait(
+	&mut input, &format!("__Override_{}_{}", self_type, priority), &trait_path));
     
     TokenStream::from(quote! {
+	#forward
+	#helper
 	#input
     })
 }
 
+/// The body dispatching to each flagged version of `ident`, as listed by
+/// `overrider_build` in `flagstrs`, falling back on `entry` when none applies.
+/// `call` turns the name of a flagged version into the expression evaluating it.
+///
+/// The flags are only looked at on the first call, which settles on an index into
+/// the versions; every later call is a single load and a jump on that index
+fn flag_dispatch(flagstrs: &str, ident: &Ident, call: impl Fn(Ident) -> proc_macro2::TokenStream,
+		 entry: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let mut checks = Vec::new();
+    let mut arms = Vec::new();
+    for (index, f) in flagstrs.split(' ').enumerate() {
+	let index = index + 1; // 0 is left to mean undecided
+	// versions known to apply, from OVERRIDER_BAKE_FLAGS, are marked with `b_`
+	let (baked, name) = match f.strip_prefix("b_") {
+	    Some(name) => (true, name),
+	    None => (false, f),
+	};
+	// and a `when` partly decided by them is followed by what's left of it to check
+	let (name, rest) = name.split_once('=').unwrap_or((name, name));
+	let version = match Version::from_name(rest) {
+	    Some(version) => version,
+	    None => return quote!(compile_error!(concat!("Unknown flagged version ", #name))),
+	};
+	let flagext = call(Ident::new(&format!("__override_flagext_{}_{}", name, ident),
+				      Span::call_site()));
+	if let Version::Cpu(feature) = &version {
+	    checks.push(quote! {
+		#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+		{
+		    if ::std::is_x86_feature_detected!(#feature) {
+			return #index;
+		    }
+		}
+	    });
+	    arms.push(quote! {
+		#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+		// SAFETY: the CPU supports everything the flagged version enables
+		#index => unsafe { #flagext },
+	    });
+	    continue;
+	}
+	arms.push(quote!(#index => #flagext,));
+	let condition = match version {
+	    _ if baked => quote!(true),
+	    Version::Flag{flag, invert: false} =>
+		quote!(crate::__overrider_flag_source().is_set(#flag)),
+	    Version::Flag{flag, invert: true} =>
+		quote!(!crate::__overrider_flag_source().is_set(#flag)),
+	    Version::Value{flag, value} =>
+		quote!(crate::__overrider_flag_source().value(#flag) == Some(#value)),
+	    Version::Count{flag, min} =>
+		quote!(crate::__overrider_flag_source().occurrences(#flag) >= #min),
+	    Version::When(when) => match When::parse_str(&when) {
+		Ok(when) => when_condition(&when),
+		Err(error) => return error.to_compile_error(),
+	    },
+	    Version::Env{var, value: Some(value)} =>
+		quote!(::std::env::var_os(#var).map_or(false, |v| v == #value)),
+	    Version::Env{var, value: None} => quote!(::std::env::var_os(#var).is_some()),
+	    Version::Field{field, invert} => {
+		let field = match syn::parse_str::<Ident>(&field) {
+		    Ok(field) => field,
+		    Err(error) => return error.to_compile_error(),
+		};
+		let not = if invert {quote!(!)} else {quote!()};
+		quote!(#not crate::__overrider_flag_parser().#field)
+	    },
+	    Version::FieldValue{field, value} => {
+		let (field, value) = match (syn::parse_str::<Ident>(&field),
+					    syn::parse_str::<syn::Pat>(&value)) {
+		    (Ok(field), Ok(value)) => (field, value),
+		    (Err(error), _) | (_, Err(error)) => return error.to_compile_error(),
+		};
+		quote!(matches!(crate::__overrider_flag_parser().#field, #value))
+	    },
+	    Version::Cpu(_) => unreachable!(),
+	};
+	checks.push(quote! {
+	    if #condition {
+		return #index;
+	    }
+	});
+    }
+    // named so as not to shadow the arguments, or anything else they're built from
+    quote! {
+	static __OVERRIDE_CHOICE: ::std::sync::atomic::AtomicUsize =
+	    ::std::sync::atomic::AtomicUsize::new(0);
+	#[allow(unreachable_code)]
+	fn __override_choose() -> usize {
+	    #(#checks)*
+	    usize::MAX
+	}
+	let __override_choice =
+	    match __OVERRIDE_CHOICE.load(::std::sync::atomic::Ordering::Relaxed) {
+		0 => {
+		    let __override_choice = __override_choose();
+		    __OVERRIDE_CHOICE.store(__override_choice,
+					    ::std::sync::atomic::Ordering::Relaxed);
+		    __override_choice
+		},
+		__override_choice => __override_choice,
+	    };
+	match __override_choice {
+	    #(#arms)*
+	    _ => #entry,
+	}
+    }
+}
+
+/// The condition checking a `when` expression at runtime
+fn when_condition(when: &When) -> proc_macro2::TokenStream {
+    match when {
+	When::Flag(flag) => quote!(crate::__overrider_flag_source().is_set(#flag)),
+	When::Not(when) => {
+	    let when = when_condition(when);
+	    quote!(!(#when))
+	},
+	When::All(whens) => {
+	    let whens = whens.iter().map(when_condition);
+	    quote!((true #(&& #whens)*))
+	},
+	When::Any(whens) => {
+	    let whens = whens.iter().map(when_condition);
+	    quote!((false #(|| #whens)*))
+	},
+    }
+}
+
+/// The helper trait member a trait item is moved into
+fn trait_member(trait_name: &str, ident: &Ident) -> Ident {
+    Ident::new(&format!("__override_trait_{}_{}", trait_name, ident), Span::call_site())
+}
+
+/// A trait can only be implimented once, so the items of a trait impl can't be
+/// overriden directly. Instead, `input` is stripped of its trait, leaving methods
+/// and constants that are overriden like any other (and renamed with `trait_member`
+/// by the caller), then made an impl of a helper trait with `helper_trait`. The trait
+/// impl returned forwards every item to those members. Only `#[default]` emits it,
+/// which is why it alone may carry associated types.
+fn split_trait_impl(input: &mut ItemImpl, default: bool)
+		    -> syn::Result<Option<(ItemImpl, String, syn::Path)>> {
+    let trait_path = match input.trait_.take() {
+	Some((_, trait_path, _)) => trait_path,
+	None => return Ok(None),
+    };
+    let trait_name = path_name(&trait_path);
+
+    let mut forward = input.clone();
+    forward.trait_ = Some((None, trait_path.clone(), Default::default()));
+    for item in &mut forward.items {
+	match item {
+	    Method(method) => {
+		let (args, receiver) = sig_args(&mut method.sig);
+		let member = trait_member(&trait_name, &method.sig.ident);
+		let self_arg = if receiver { Some(quote!(self,)) } else { None };
+		method.block = syn::parse2(quote! {{
+		    Self::#member(#self_arg #(#args),*)
+		}})?;
+	    },
+	    Const(constant) => {
+		let member = trait_member(&trait_name, &constant.ident);
+		constant.expr = syn::parse2(quote!(Self::#member))?;
+	    },
+	    item if !default => return Err(syn::Error::new(
+		item.span(),
+		"Only methods/consts of a trait impl can be overriden. \
+		 Everything else belongs on the #[default] impl block")),
+	    _ => {},
+	}
+    }
+
+    // Whatever isn't a method or const stays with the trait impl
+    input.unsafety = None;
+    input.items.retain(|item| matches!(item, Method(_) | Const(_)));
+    let mut qualify = QualifySelf(&trait_path);
+    for item in &mut input.items {
+	qualify.visit_impl_item_mut(item);
+    }
+
+    Ok(Some((forward, trait_name, trait_path)))
+}
+
+/// Makes what's left of a trait impl by `split_trait_impl` an impl of a new helper
+/// trait, `name`, returning the trait. It requires `trait_path`, so that
+/// `<Self as Trait>::Item` means something in it. Inherent members would do, were the type
+/// always local to the crate; a trait can be implemented for `u32` too.
+/// Every block of a trait impl gets its own helper, all in the same module,
+/// so the forwarding impl finds whichever member is compiled in
+fn helper_trait(input: &mut ItemImpl, name: &str, trait_path: &syn::Path)
+		-> proc_macro2::TokenStream {
+    let name = Ident::new(name, Span::call_site());
+    let members = input.items.iter().map(|item| {
+	let cfgs = |attrs: &[Attribute]| attrs.iter()
+	    .filter(|attr| attr.path.is_ident("cfg")).cloned().collect::<Vec<_>>();
+	match item {
+	    Method(method) => {
+		let cfgs = cfgs(&method.attrs);
+		let mut sig = method.sig.clone();
+		sig_args(&mut sig);
+		for input in sig.inputs.iter_mut() {
+		    if let syn::FnArg::Typed(t) = input {
+			t.pat = syn::parse_quote!(_); // no patterns without a body
+		    }
+		}
+		quote!(#(#cfgs)* #sig;)
+	    },
+	    Const(constant) => {
+		let cfgs = cfgs(&constant.attrs);
+		let (ident, ty) = (&constant.ident, &constant.ty);
+		quote!(#(#cfgs)* const #ident: #ty;)
+	    },
+	    _ => quote!(),
+	}
+    }).collect::<Vec<_>>();
+    for item in &mut input.items {
+	match item {
+	    Method(method) => method.vis = syn::Visibility::Inherited,
+	    Const(constant) => constant.vis = syn::Visibility::Inherited,
+	    _ => {},
+	}
+    }
+    let generics = &input.generics;
+    let where_clause = &generics.where_clause;
+    let (_, ty_generics, _) = generics.split_for_impl();
+    input.trait_ = Some((None, syn::parse_quote!(#name #ty_generics), Default::default()));
+    quote! {
+	#[doc(hidden)]
+	#[allow(non_camel_case_types, dead_code)]
+	trait #name #generics: #trait_path #where_clause {
+	    #(#members)*
+	}
+    }
+}
+
+/// `Self::Output` is ambiguous outside of the trait impl,
+/// so spell it out as `<Self as Trait>::Output`
+struct QualifySelf<'a>(&'a syn::Path);
+
+impl VisitMut for QualifySelf<'_> {
+    fn visit_type_path_mut(&mut self, type_path: &mut syn::TypePath) {
+	if type_path.qself.is_none() && type_path.path.segments.len() > 1
+	    && type_path.path.segments[0].ident == "Self" {
+		let trait_path = self.0;
+		let rest = type_path.path.segments.iter().skip(1);
+		*type_path = syn::parse_quote!(<Self as #trait_path>#(::#rest)*);
+	    }
+	syn::visit_mut::visit_type_path_mut(self, type_path);
+    }
+}
+
+/// Grab the arguments of a signature so they can be passed along,
+/// and whether it takes `self`. Patterns such as `(a, b): (u8, u8)` are named
+/// `__override_arg{n}` in `sig`, which only forwards them to a function keeping the pattern
+fn sig_args(sig: &mut syn::Signature) -> (Vec<Ident>, bool) {
+    let mut args = Vec::new();
+    let mut receiver = false;
+    for (n, input) in sig.inputs.iter_mut().enumerate() {
+	match input {
+	    syn::FnArg::Typed(t) => match t.pat.as_ref() {
+		syn::Pat::Ident(p) if p.by_ref.is_none() && p.subpat.is_none() =>
+		    args.push(p.ident.clone()),
+		_ => {
+		    let arg = Ident::new(&format!("__override_arg{}", n), Span::call_site());
+		    *t.pat = syn::parse_quote!(#arg);
+		    args.push(arg);
+		},
+	    },
+	    syn::FnArg::Receiver(_) => {
+		receiver = true;
+	    },
+	}
+    };
+    (args, receiver)
+}
+
 fn attr_add(attrs: &mut Vec<Attribute>, flag: String) {
     let override_flag = Ident::new(&flag, Span::call_site());
     attrs.push(
@@ -590,114 +1163,318 @@ fn attr_inline(attrs: &mut Vec<Attribute>) {
 	).unwrap().attrs.swap_remove(0));
 }
 
+/// Flagged versions are inlined into their dispatcher. Versions for a CPU feature are
+/// compiled with it enabled instead, which can't be inlined into code without it
+fn attr_variant(attrs: &mut Vec<Attribute>, cpu: Option<&syn::LitStr>) {
+    match cpu {
+	Some(feature) => {
+	    attrs.push(syn::parse_quote!(#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]));
+	    attrs.push(syn::parse_quote!(#[target_feature(enable = #feature)]));
+	},
+	None => attr_inline(attrs),
+    }
+}
+
 /// Override a base implimentation, but only when runtime is called with certain flags
 ///
-/// Attaching this attribute to a `fn` or `impl` block enables it to be overriden at runtime
-/// depending on what flags are passed to the executable. Flags require `lazy_static` and
-/// `clap` to work properly. The expected format is as follows:
-/// ```
+/// Attaching this attribute to a `fn`, `impl` block or `static` enables it to be overriden at
+/// runtime depending on what flags are passed to the executable. Flags are read from a
+/// `overrider_runtime::FlagSource`, which must be registered at the root of the crate:
+/// ```ignore
 /// lazy_static! {
 ///     static ref CLAP_FLAGS: ArgMatches<'static> = {
-/// 	App::new("Overrider example - flag")
+///         App::new("Overrider example - flag")
 ///             .arg(Arg::with_name("change").long("change"))
 ///             .get_matches()
 ///     };
 /// }
+///
+/// overrider_runtime::flag_source!(&*CLAP_FLAGS);
+/// ```
+/// `overrider_runtime` comes with sources for `clap` 2 and 4 (behind the `clap2` and
+/// `clap4` features), for TOML or JSON configuration files (`ConfigFlags`, behind the
+/// `toml` and `json` features) and for closures, such as
+/// `|flag: &str| std::env::var_os(flag).is_some()`. A pair of sources reads both, the first
+/// winning. Anything else can impliment `FlagSource` itself.
+///
+/// Rather than declaring each flag again for `clap`, `overrider_build` lists every one
+/// used (with help from doc comments of flagged items) for including:
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/overrider_flags.rs"));
+///
+/// App::new("example").args(&OVERRIDER_FLAGS.iter().map(|flag| flag.clap2()).collect::<Vec<_>>())
 /// ```
-/// `overrider` expects this format. `CLAP_FLAGS` must be a `clap::ArgMatches` object,
-/// and it must be done through `lazy_static`. This object must be referenced consistantly,
-/// so if work is spread across multiple files, the single `CLAP_FLAGS` instant must be
-/// imported. `overrider` expects `CLAP_FLAGS` in the local namespace.
-/// It's a bit annoying, but it offers unparalleled performance and ease of use
-/// after the setup stage.
 ///
 /// ## Syntax
-/// After the `CLAP_FLAGS` definition mentioned above, the `#[override_flag]` attribute can
+/// After registering a flag source as mentioned above, the `#[override_flag]` attribute can
 /// be attached to an item. **A `#[default]` implimentation is required**. This is so the item
 /// is not left undefined if no flags are passed.
 ///
 /// `override_flag` takes two arguements:
 /// - flag
-/// - priority  
+/// - priority
+///
 /// Priority allows for overriding a previous flag definition. The full syntax is as follows:  
-/// `#[override_flag(flag = FLAGNAME, priority = n)]`, where `FLAGNAME` is a UTF8 string
-/// containing no spaces, and `n` is a positive integer.
+/// `#[override_flag(flag = FLAGNAME, priority = n)]`, where `FLAGNAME` is an identifier,
+/// or a string literal for names like `"dry-run"`, and `n` is a positive integer.
+///
+/// Priority also decides between different flags. When several are set, the version with
+/// the highest priority wins. This goes for every kind of flag on an item (including
+/// `#[override_cpu]` and `#[override_env]`), so giving two of them the same priority is a
+/// build error, unless they can never both apply.
+///
+/// The flags are only checked on the first call, which picks the version every later
+/// call goes to.
+/// Flags can also be pinned at build time with `OVERRIDER_BAKE_FLAGS` (see
+/// `overrider_build`), so the version is picked without a dispatcher at all.
+///
+/// ### Values
+/// Rather than just being set, a flag can be required to hold a value, as with `--mode=fast`:
+/// ```ignore
+/// #[override_flag(flag = mode, value = "fast")]
+/// fn run() {}
+///
+/// #[override_flag(flag = mode, value = "safe")]
+/// fn run() {}
+/// ```
+/// The `#[default]` is used when the flag isn't given, or holds any other value.
+///
+/// ### Occurrences
+/// For flags that can be given more than once, such as `-vv`, `min_occurrences` requires
+/// the flag at least that many times. Among versions of the same priority, the one with
+/// the highest count that's met wins:
+/// ```ignore
+/// #[override_flag(flag = v)]
+/// fn log() {} // -v
+///
+/// #[override_flag(flag = v, min_occurrences = 2)]
+/// fn log() {} // -vv, -vvv, ...
+/// ```
+///
+/// ### Expressions
+/// In place of `flag`, `when` takes an expression of flags, for a version that depends on
+/// more than one of them. Flags can be combined with `&&`, `||` and `!`, or with
+/// `any(..)`, `all(..)` and `not(..)` as in `#[cfg]`. Flag names that aren't valid
+/// identifiers can be written as strings:
+/// ```ignore
+/// #[override_flag(when = "a && !b")]
+/// fn foo() {}
+///
+/// #[override_flag(when = "any(verbose, \"dry-run\")")]
+/// fn bar() {}
+/// ```
+///
+/// ### Fields
+/// Where the command line is parsed into a struct, such as with `clap`'s
+/// `#[derive(Parser)]`, `field` reads one of its fields in place of a flag. The struct is
+/// registered once per crate with `overrider_runtime::flag_parser!`. A `bool` field picks
+/// a version when it's true (or false, with `invert = true`), and any field can be matched
+/// against a pattern with `value`:
+/// ```ignore
+/// overrider_runtime::flag_parser!(Cli, &*CLI);
+///
+/// #[override_flag(field = verbose)]
+/// fn log() {}
+///
+/// #[override_flag(field = mode, value = "Mode::Fast")]
+/// fn run() {}
+/// ```
+/// Fields are only known at runtime, so aren't baked or listed by `overrider_build`.
 #[proc_macro_attribute]
 pub fn override_flag(attr: TokenStream, input: TokenStream) -> TokenStream {
-    // parse 3 arguements (flag = x [, priority = N] [, invert = (true|false)])
-    // invert is handled in build stage
-    let mut flag = None;
-    let mut priority = 0;
-    let mut _invert = false;
-
-    let attrstr = attr.to_string();
-    for arg in attrstr.split(",") {
-	let mut iter = arg.split("=");
-	let left = iter.next().expect("Malformed arguement").trim();
-	let right = iter.next().expect("Malformed arguement").trim();
-	match left {
-	    "flag" => flag = Some(right),
-	    "priority" => priority = right.parse().expect(&format!("Invalid arguement '{}'", right)),
-	    "invert" => _invert = right.parse().expect(&format!("Invalid arguement '{}'", right)),
-	    _ => panic!("Invalid arguement '{}'", right),
-	}
-    }
+    let FlagArgs{version, priority} = syn::parse_macro_input!(attr as FlagArgs);
+    flag_item(input, priority, version.name())
+}
 
-    if flag.is_none() {
-	return quick_error("Missing mandatory arguement 'flag'".to_string());
+/// Anything that can be flagged, under the version name `flag`
+fn flag_item(input: TokenStream, priority: u32, flag: String) -> TokenStream {
+    if let Ok(item) = syn::parse::<ItemImpl>(input.clone()) {
+	flag_impl(item, priority, flag, None)
+    } else if let Ok(item) = syn::parse::<ItemFn>(input.clone()) {
+	flag_function(item, priority, flag, None)
+    } else if let Ok(item) = syn::parse::<ItemStatic>(input.clone()) {
+	flag_static(item, priority, flag)
+    } else if let Ok(item) = syn::parse::<ItemConst>(input) {
+	syn::Error::new(item.const_token.span(),
+			"A const must be known at compile time, so can't be flagged. \
+			 Try a static instead")
+	    .to_compile_error().into()
+    } else {
+	quick_error("I can't parse this yet".to_string())
     }
+}
 
+/// Override a base implementation, but only when an environment variable is set
+///
+/// Attaching this attribute to a `fn`, `impl` block or `static` enables it to be overriden at
+/// runtime depending on the environment the program is run in. It works just like
+/// [`#[override_flag]`](attr.override_flag.html), except no flag source is needed. The
+/// variable is read on first use, and the choice kept from then on.
+/// **A `#[default]` implementation is required**.
+///
+/// ## Syntax
+/// `override_env` takes a `var` naming the environment variable, and either a `value` it
+/// must be set to, or `set = true` to accept any value. `priority` is optional, and
+/// works as it does for flags.
+/// ```ignore
+/// #[default]
+/// fn allocator() -> &'static str { "system" }
+///
+/// #[override_env(var = "APP_ALLOCATOR", value = "jemalloc")]
+/// fn allocator() -> &'static str { "jemalloc" }
+///
+/// #[override_env(var = "APP_DEBUG", set = true)]
+/// fn verbose() -> bool { true }
+/// ```
+#[proc_macro_attribute]
+pub fn override_env(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let EnvArgs{version, priority} = syn::parse_macro_input!(attr as EnvArgs);
+    flag_item(input, priority, version.name())
+}
+
+/// Override a base implementation, but only when running on a CPU with certain features
+///
+/// Attaching this attribute to a `fn` or `impl` block (a trait impl included) enables it to
+/// be overriden at runtime, depending on what the CPU supports. It works much like
+/// [`#[override_flag]`](attr.override_flag.html), except the CPU is checked (with
+/// `is_x86_feature_detected!`) instead of a flag source. The check happens once, on the first
+/// call, and is remembered from then on. **A `#[default]` implementation is required**, for
+/// when the feature is missing.
+///
+/// The overriding version is compiled with the feature enabled, as if by
+/// `#[target_feature(enable = "...")]`, so it's free to use intrinsics from `std::arch`
+/// or leave the compiler to vectorize. It's only compiled for x86 and x86_64 targets;
+/// elsewhere the `#[default]` is always used. In a trait impl, the methods are compiled
+/// into `unsafe` members of a hidden trait, which only the trait impl calls.
+///
+/// ## Syntax
+/// `override_cpu` takes two arguments:
+/// - feature, any feature `is_x86_feature_detected!` knows of
+/// - priority (optional)
+///
+/// ```ignore
+/// #[default]
+/// fn sum(values: &[f32]) -> f32 {
+///     values.iter().sum()
+/// }
+///
+/// #[override_cpu(feature = "avx2", priority = 2)]
+/// fn sum(values: &[f32]) -> f32 {
+///     values.iter().sum() // vectorized with avx2
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn override_cpu(attr: TokenStream, input: TokenStream) -> TokenStream {
+    let args = syn::parse_macro_input!(attr as CpuArgs);
+    let (name, feature, priority) = (args.version().name(), args.feature, args.priority);
     if let Ok(item) = syn::parse::<ItemImpl>(input.clone()) {
-	flag_impl(item, priority, flag.unwrap().to_string())
+	flag_impl(item, priority, name, Some(&feature))
     } else if let Ok(item) = syn::parse::<ItemFn>(input) {
-	flag_function(item, priority, flag.unwrap().to_string())
+	flag_function(item, priority, name, Some(&feature))
     } else {
-	quick_error(format!("I can't parse this yet"))
+	quick_error("Only a `fn` or `impl` block can depend on the CPU".to_string())
     }
 }
 
+/// Whether `overrider_build` settled on the `flag` version of `key` from the flags in
+/// `OVERRIDER_BAKE_FLAGS`, in which case it's compiled in place of the `#[default]`
+fn baked(key: &str, flag: &str) -> bool {
+    std::env::var(format!("__override_baked_{}", key)).is_ok_and(|baked| baked == flag)
+}
 
-fn flag_function(mut item: ItemFn, priority: u32, flag: String) -> TokenStream {
-    attr_add(&mut item.attrs,
-	     format!("__override_priority_{}_flag_{}_func_{}",
-		     priority, flag, item.sig.ident));
-    attr_inline(&mut item.attrs);
+/// `flag` names the version. Versions for a CPU feature also give the `cpu` feature
+fn flag_function(mut item: ItemFn, priority: u32, flag: String, cpu: Option<&syn::LitStr>)
+		 -> TokenStream {
+    let key = format!("func_{}{}", module_prefix(), item.sig.ident);
+    attr_add(&mut item.attrs, format!("__override_priority_{}_flag_{}_{}", priority, flag, key));
+    if baked(&key, &flag) {
+	return TokenStream::from(quote! {
+	    #item
+	});
+    }
+    attr_variant(&mut item.attrs, cpu);
     item.sig.ident = Ident::new(&format!("__override_flagext_{}_{}",
 					 flag, item.sig.ident),
 				Span::call_site());
-    return TokenStream::from(quote! {
+    TokenStream::from(quote! {
 	#item
-    });
+    })
 }
 
-fn flag_impl(mut impl_block: ItemImpl, priority: u32, flag: String) -> TokenStream {
-    let self_type = match impl_block.self_ty.as_ref() {
-	Path(path) => path,
-	_ => return quick_error(format!("Could not get Path for impl (should never see this)")),
-    }.path.segments[0].ident.to_string();
+/// The flagged value of a static, read by the handle `attach_static` generates
+fn flag_static(item: ItemStatic, priority: u32, flag: String) -> TokenStream {
+    let ItemStatic{mut attrs, vis, mutability, ident, mut ty, expr, ..} = item;
+    if let Some(mutability) = mutability {
+	return syn::Error::new(mutability.span(), "A flagged static can't be mutable")
+	    .to_compile_error().into();
+    }
+    let key = format!("static_{}{}", module_prefix(), ident);
+    attr_add(&mut attrs, format!("__override_priority_{}_flag_{}_{}", priority, flag, key));
+    StaticLifetimes.visit_type_mut(&mut ty);
+    if baked(&key, &flag) { // still a handle, so it's used the same way
+	return static_handle(&attrs, &vis, &ident, &ty, quote!(#expr)).into();
+    }
+    attr_inline(&mut attrs);
+    let flagext = Ident::new(&format!("__override_flagext_{}_{}", flag, ident),
+			     Span::call_site());
+    TokenStream::from(quote! {
+	#(#attrs)*
+	#[allow(non_snake_case)]
+	#vis fn #flagext() -> #ty {
+	    #expr
+	}
+    })
+}
+
+fn flag_impl(mut impl_block: ItemImpl, priority: u32, flag: String, cpu: Option<&syn::LitStr>)
+	     -> TokenStream {
+    let self_type = match impl_name(&impl_block) {
+	Ok(self_type) => format!("{}{}", module_prefix(), self_type),
+	Err(error) => return error.to_compile_error().into(),
+    };
+    // The #[default] trait impl forwards to these, so the trait impl itself is dropped
+    let (trait_name, trait_path) = match split_trait_impl(&mut impl_block, false) {
+	Ok(Some((_, trait_name, trait_path))) => (Some(trait_name), Some(trait_path)),
+	Ok(None) => (None, None),
+	Err(error) => return error.to_compile_error().into(),
+    };
     for item in &mut impl_block.items {
 	match item {
 	    Method(method) => {
+		let key = format!("method_{}_{}", self_type, method.sig.ident);
 		attr_add(&mut method.attrs,
-			 format!("__override_priority_{}_flag_{}_method_{}_{}",
-				 priority, flag, self_type, method.sig.ident));
-		attr_inline(&mut method.attrs);
+			 format!("__override_priority_{}_flag_{}_{}", priority, flag, key));
+		if let Some(trait_name) = &trait_name {
+		    method.sig.ident = trait_member(trait_name, &method.sig.ident);
+		}
+		if baked(&key, &flag) {
+		    continue;
+		}
+		attr_variant(&mut method.attrs, cpu);
+		if cpu.is_some() && trait_name.is_some() {
+		    // a trait method can only enable a feature when unsafe to call, as the
+		    // helper trait's member is here. The dispatcher checks the CPU first
+		    method.sig.unsafety = Some(Default::default());
+		}
 		method.sig.ident = Ident::new(&format!("__override_flagext_{}_{}",
 						       flag, method.sig.ident),
 					      Span::call_site());
 	    },
-	    Const(_constant) => {
-		return quick_error(format!("flagging a constant currently envokes undefined \
-					    behavior"));
-	    },
+	    Const(constant) => return syn::Error::new(
+		constant.span(),
+		"A const must be known at compile time, so can't be flagged. Try a static instead")
+		.to_compile_error().into(),
 	    item => return syn::Error::new(
 		item.span(),
-		format!("I can't overload anything other than methods/consts in an impl \
-			 block yet"))
+		"I can't overload anything other than methods/consts in an impl \
+		 block yet")
 		.to_compile_error().into(),
 	}
     }
+    let helper = trait_path.map(|trait_path| helper_trait(&mut impl_block, &format!(
+	"__Override_{}_flag_{}_{}", self_type, flag, priority), &trait_path));
     TokenStream::from(quote! {
+	#helper
 	#impl_block
     })
 }
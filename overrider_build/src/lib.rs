@@ -8,72 +8,209 @@
 //!     overrider_build::watch_files(vec!["src/main.rs"]);
 //! }
 //! ```
+//! Or, to have every file in the crate found by following its `mod` declarations,
+//! `watch_crate`:
+//! ```no_run
+//! fn main() {
+//!     overrider_build::watch_crate();
+//! }
+//! ```
 
-use syn::{Type::Path, ImplItem::{Method, Const}};
+#![allow(clippy::needless_doctest_main)] // build scripts are all about `fn main`
+
+use syn::{ImplItem::{Method, Const}, ItemMod};
+use syn::visit::Visit;
+use quote::ToTokens;
 use std::fs::File;
+use std::path::{self, PathBuf};
 use std::io::Read;
+use std::collections::HashMap;
+use overrider_common::{file_id, impl_name, use_names, CpuArgs, EnvArgs, FlagArgs, Version, When};
 use glob::glob;
 
-enum Status {Norm(u32), Flag(String, u32, bool), Final, Empty}
+enum Status {
+    Norm(u32),
+    Flagged(Version, u32),
+    Final,
+    Empty,
+}
 use Status::*;
-fn get_priority(attrs: &Vec<syn::Attribute>) -> Status {
+fn get_priority(attrs: &[syn::Attribute]) -> Status {
     for attr in attrs { // there's no error checking; overrider main can give richer error messages
-	if attr.path.segments[0].ident.to_string() == "override_default" {
-	    if let Ok(syn::Expr::Paren(expr)) = syn::parse2::<syn::Expr>(attr.tokens.clone()) {
-		if let syn::Expr::Assign(assign) = *expr.expr {
-		    if let syn::Expr::Path(left) = *assign.left {
-			if left.path.segments.len() == 1
-			    && left.path.segments[0].ident.to_string() == "priority" {
-				if let syn::Expr::Lit(lit) = *assign.right {
-				    if let syn::Lit::Int(i) = lit.lit {
-					if let Ok(priority) = i.base10_parse::<u32>() {
-					    return Norm(priority);
-					}
-				    }
-				}
-			    }
-		    }
-		}
-	    } else { // might be default
-		if attr.tokens.is_empty() {
-		    return Norm(1);
-		}
+	if attr.path.segments[0].ident == "override_default" {
+	    if attr.tokens.is_empty() { // might be default
+		return Norm(1);
 	    }
-	} else if attr.path.segments[0].ident.to_string() == "override_flag" {
-	    if !attr.tokens.is_empty() { // TODO;
-		let mut flag = None;
-		let mut priority = 0;
-		let mut invert = false;
-		let attrstr = attr.tokens.to_string();
-		for arg in attrstr.trim_start_matches('(').trim_end_matches(')').split(",") {
-		    let mut iter = arg.split("=");
-		    let left = iter.next().expect("Malformed arguement").trim();
-		    let right = iter.next().expect("Malformed arguement").trim();
-		    match left {
-			"flag" => flag = Some(right),
-			"priority" => priority = right.parse().expect(&format!("Invalid arguement '{}'", right)),
-			"invert" => invert = right.parse().expect(&format!("Invalid arguement '{}'", right)),
-			_ => panic!("Invalid arguement '{}'", right),
-		    }
-		}
-		if flag.is_none() {
-		    return Empty;
-		}
-		return Flag(flag.unwrap().to_string(), priority, invert);
+	    if let Some(status) = gated_priority(attr, false) {
+		return status;
 	    }
-	} else if attr.path.segments[0].ident.to_string() == "default" {
-	    if attr.tokens.is_empty() {
-		return Norm(0);
+	} else if attr.path.segments[0].ident == "override_cfg" {
+	    if let Some(status) = gated_priority(attr, true) {
+		return status;
 	    }
-	} else if attr.path.segments[0].ident.to_string() == "override_final" {
-	    if attr.tokens.is_empty() {
-		return Final;
+	} else if attr.path.segments[0].ident == "override_flag" {
+	    if let Ok(FlagArgs{version, priority}) = attr.parse_args() {
+		return Flagged(version, priority);
 	    }
+	} else if attr.path.segments[0].ident == "override_cpu" {
+	    if let Ok(args) = attr.parse_args::<CpuArgs>() {
+		return Flagged(args.version(), args.priority);
+	    }
+	} else if attr.path.segments[0].ident == "override_env" {
+	    if let Ok(EnvArgs{version, priority}) = attr.parse_args() {
+		return Flagged(version, priority);
+	    }
+	} else if attr.path.segments[0].ident == "default" && attr.tokens.is_empty() {
+	    return Norm(0);
+	} else if attr.path.segments[0].ident == "override_final" && attr.tokens.is_empty() {
+	    return Final;
 	}
     }
     Empty
 }
 
+/// `(priority = N, ...)`, where the rest are cfg predicates that must hold for the item
+/// to take part. `#[override_default]` only takes `feature = "..."`, whereas
+/// `#[override_cfg]` takes `any_predicate`
+fn gated_priority(attr: &syn::Attribute, any_predicate: bool) -> Option<Status> {
+    let list = match attr.parse_meta() {
+	Ok(syn::Meta::List(list)) => list,
+	_ => return None,
+    };
+    let mut priority = 1;
+    let mut enabled = true;
+    for arg in &list.nested {
+	match arg {
+	    syn::NestedMeta::Meta(syn::Meta::NameValue(arg)) if arg.path.is_ident("priority") =>
+		priority = match &arg.lit {
+		    syn::Lit::Int(i) => i.base10_parse::<u32>().ok()?,
+		    _ => return None,
+		},
+	    syn::NestedMeta::Meta(syn::Meta::NameValue(feature))
+		if feature.path.is_ident("feature") =>
+		enabled = enabled && eval_cfg(arg).unwrap_or(true),
+	    predicate if any_predicate => enabled = enabled && eval_cfg(predicate).unwrap_or(true),
+	    _ => return None,
+	}
+    }
+    Some(if enabled {
+	Norm(priority)
+    } else {
+	Empty // compiled out
+    })
+}
+
+/// Whether the `#[cfg(...)]`s on an item will let it be compiled. Build scripts are told
+/// about enabled features and the target with `CARGO_FEATURE_*` and `CARGO_CFG_*`.
+/// Anything that can't be known here is assumed to be compiled
+fn cfg_enabled(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().filter(|attr| attr.path.is_ident("cfg")).all(|attr| {
+	match attr.parse_meta() {
+	    Ok(syn::Meta::List(list)) if list.nested.len() == 1 =>
+		eval_cfg(&list.nested[0]).unwrap_or(true),
+	    _ => true, // malformed, which rustc will point out
+	}
+    })
+}
+
+/// `None` when the predicate can't be decided from a build script
+fn eval_cfg(predicate: &syn::NestedMeta) -> Option<bool> {
+    let meta = match predicate {
+	syn::NestedMeta::Meta(meta) => meta,
+	syn::NestedMeta::Lit(_) => return None,
+    };
+    let env_name = |path: &syn::Path| path.get_ident()
+	.map(|ident| ident.to_string().to_uppercase().replace('-', "_"));
+    match meta {
+	syn::Meta::Path(path) => match env_name(path)?.as_str() {
+	    "TEST" | "DOC" | "DOCTEST" => None, // the same build script serves all of these
+	    name => Some(std::env::var_os(format!("CARGO_CFG_{}", name)).is_some()),
+	},
+	syn::Meta::NameValue(syn::MetaNameValue{path, lit: syn::Lit::Str(value), ..}) => {
+	    let name = env_name(path)?;
+	    let value = value.value();
+	    if name == "FEATURE" {
+		Some(std::env::var_os(format!("CARGO_FEATURE_{}",
+					      value.to_uppercase().replace('-', "_"))).is_some())
+	    } else {
+		Some(std::env::var(format!("CARGO_CFG_{}", name))
+		     .map(|values| values.split(',').any(|v| v == value))
+		     .unwrap_or(false))
+	    }
+	},
+	syn::Meta::NameValue(_) => None,
+	syn::Meta::List(list) => {
+	    let mut results = list.nested.iter().map(eval_cfg);
+	    if list.path.is_ident("not") && list.nested.len() == 1 {
+		results.next()?.map(|result| !result)
+	    } else if list.path.is_ident("all") {
+		// One false settles it, even when something else is unknown
+		results.try_fold(Some(true), |all, result| match result {
+		    Some(false) => Err(()),
+		    Some(true) => Ok(all),
+		    None => Ok(None),
+		}).unwrap_or(Some(false))
+	    } else if list.path.is_ident("any") {
+		results.try_fold(Some(false), |any, result| match result {
+		    Some(true) => Err(()),
+		    Some(false) => Ok(any),
+		    None => Ok(None),
+		}).unwrap_or(Some(true))
+	    } else {
+		None
+	    }
+	},
+    }
+}
+
+/// One run of a build script serves builds with and without `test`, `doc` and
+/// `doctest`, so an override behind one of them would win or lose in every build at
+/// once, leaving the others with two versions of an item or none. That goes for the
+/// predicates of `#[override_cfg]` as well as `#[cfg]`. `status` is the attributes saying
+/// whether something's overriden, which `attrs` may be part of
+fn check_build_cfgs(attrs: &[syn::Attribute], status: &[syn::Attribute], file: &str) {
+    fn per_build(predicate: &syn::NestedMeta) -> bool {
+	match predicate {
+	    syn::NestedMeta::Meta(syn::Meta::Path(path)) =>
+		["test", "doc", "doctest"].iter().any(|name| path.is_ident(name)),
+	    syn::NestedMeta::Meta(syn::Meta::List(list)) => list.nested.iter().any(per_build),
+	    _ => false,
+	}
+    }
+    if let Empty = get_priority(status) {
+	return;
+    }
+    let gates = attrs.iter()
+	.filter(|attr| attr.path.is_ident("cfg") || attr.path.is_ident("override_cfg"));
+    for attr in gates {
+	if let Ok(syn::Meta::List(list)) = attr.parse_meta() {
+	    if list.nested.iter().any(per_build) {
+		panic!("{}:{}: #[{}({})] can't be on an overriden or flagged item, as the same \
+			build script serves builds with and without it. Put the whole chain of \
+			overrides in a module behind it instead",
+		       file, attr.pound_token.span.start().line, list.path.to_token_stream(),
+		       list.nested.to_token_stream());
+	    }
+	}
+    }
+}
+
+/// The attributes of anything `Scan` looks at
+fn item_attrs(item: &syn::Item) -> &[syn::Attribute] {
+    match item {
+	syn::Item::Fn(item) => &item.attrs,
+	syn::Item::Static(item) => &item.attrs,
+	syn::Item::Impl(item) => &item.attrs,
+	syn::Item::Struct(item) => &item.attrs,
+	syn::Item::Enum(item) => &item.attrs,
+	syn::Item::Const(item) => &item.attrs,
+	syn::Item::Type(item) => &item.attrs,
+	syn::Item::Mod(item) => &item.attrs,
+	syn::Item::Use(item) => &item.attrs,
+	_ => &[],
+    }
+}
+
 
 #[derive(Debug)]
 struct Override {
@@ -84,8 +221,318 @@ struct Override {
 #[derive(Debug)]
 struct Flagger {
     pub sig: String,
-    pub flag: String,
+    pub version: Version,
     pub priority: u32,
+    pub doc: String,
+    pub location: String,
+}
+
+/// Everything found while scanning files
+#[derive(Default)]
+struct Scan {
+    overrides: Vec<Override>,
+    finals:    Vec<String>,
+    flags:     Vec<Flagger>,
+    file:      String, // the one being scanned, for pointing at flags
+}
+
+impl Scan {
+    /// Items that can't be flagged are either overriden or finalized
+    fn item(&mut self, attrs: &[syn::Attribute], sig: String) {
+	match get_priority(attrs) {
+	    Norm(priority) => self.overrides.push(Override{sig, priority}),
+	    Final => self.finals.push(sig),
+	    Flagged(..) | Empty => {},
+	}
+    }
+
+    /// Items that are overriden, finalized, or flagged
+    fn flaggable(&mut self, attrs: &[syn::Attribute], sig: String) {
+	match get_priority(attrs) {
+	    Norm(priority) => self.overrides.push(Override{sig, priority}),
+	    Flagged(version, priority) => {
+		let line = attrs.iter().find(|attr| attr.path.is_ident("override_flag"))
+		    .map_or(0, |attr| attr.pound_token.span.start().line);
+		self.flags.push(Flagger{sig, version, priority, doc: doc_summary(attrs),
+					location: format!("{}:{}", self.file, line)});
+	    },
+	    Final => self.finals.push(sig),
+	    Empty => {},
+	}
+    }
+
+    /// Step over everything in `items`, which live in the module named by `prefix`
+    /// (`foo__bar__` for `foo::bar`, nothing for the crate root). Inline modules and
+    /// function bodies are noted in `modules` by line, so that `overrider` can work
+    /// out the same prefix
+    fn items<'a>(&mut self, items: impl IntoIterator<Item = &'a syn::Item>, prefix: &str,
+		 modules: &mut String) {
+	for item in items {
+	    if !cfg_enabled(item_attrs(item)) {
+		continue; // won't be compiled, so can't win
+	    }
+	    check_build_cfgs(item_attrs(item), item_attrs(item), &self.file);
+	    match item {
+		syn::Item::Fn(func) => {
+		    self.flaggable(&func.attrs, format!("func_{}{}", prefix, func.sig.ident));
+		    let body = body_name(&func.attrs, &func.sig.ident.to_string());
+		    self.body(&func.block, &format!("{}{}__", prefix, body), modules);
+		},
+		syn::Item::Static(item) =>
+		    self.flaggable(&item.attrs, format!("static_{}{}", prefix, item.ident)),
+		syn::Item::Impl(impl_block) => {
+		    let self_type = match impl_name(impl_block) { // The `Dummy` in `impl Dummy {}`
+			Ok(self_type) => self_type,
+			Err(_) => continue,
+		    };
+		    for item in &impl_block.items {
+			match item {
+			    Method(method) if !cfg_enabled(&method.attrs) => continue,
+			    Const(constant) if !cfg_enabled(&constant.attrs) => continue,
+			    Method(method) =>
+				check_build_cfgs(&method.attrs, &impl_block.attrs, &self.file),
+			    Const(constant) =>
+				check_build_cfgs(&constant.attrs, &impl_block.attrs, &self.file),
+			    _ => {},
+			}
+			match item {
+			    Method(method) => {
+				self.flaggable(&impl_block.attrs, format!("method_{}{}_{}",
+									  prefix,
+									  self_type,
+									  &method.sig.ident));
+				let body = body_name(&impl_block.attrs,
+						     &format!("{}_{}", self_type, method.sig.ident));
+				self.body(&method.block, &format!("{}{}__", prefix, body), modules);
+			    },
+			    Const(constant) =>
+				self.flaggable(&impl_block.attrs, format!("implconst_{}{}_{}",
+									  prefix,
+									  self_type,
+									  &constant.ident)),
+			    _ => continue,
+			}
+		    }
+		},
+		syn::Item::Struct(item) =>
+		    self.item(&item.attrs, format!("type_{}{}", prefix, item.ident)),
+		syn::Item::Enum(item) =>
+		    self.item(&item.attrs, format!("type_{}{}", prefix, item.ident)),
+		syn::Item::Const(item) =>
+		    self.item(&item.attrs, format!("const_{}{}", prefix, item.ident)),
+		syn::Item::Type(item) =>
+		    self.item(&item.attrs, format!("type_{}{}", prefix, item.ident)),
+		syn::Item::Mod(item) => {
+		    self.item(&item.attrs, format!("mod_{}{}", prefix, item.ident));
+		    if let Some((brace, items)) = &item.content {
+			let prefix = format!("{}{}__", prefix, module_name(item));
+			modules.push_str(&format!(";{}={}", span_range(brace.span), prefix));
+			self.items(items, &prefix, modules);
+		    }
+		},
+		syn::Item::Use(item) => {
+		    let mut names = Vec::new();
+		    use_names(&item.tree, None, &mut names);
+		    for name in names {
+			self.item(&item.attrs, format!("type_{}{}", prefix, name));
+		    }
+		},
+		_ => {} // can't parse everything yet
+	    }
+	}
+    }
+
+    /// Items declared inside a function body, anywhere from the top of it to deep
+    /// within a closure, are scoped to that body
+    fn body(&mut self, block: &syn::Block, prefix: &str, modules: &mut String) {
+	let mut nested = NestedItems::default();
+	nested.visit_block(block);
+	if nested.0.is_empty() {
+	    return;
+	}
+	modules.push_str(&format!(";{}={}", span_range(block.brace_token.span), prefix));
+	self.items(nested.0, prefix, modules);
+    }
+}
+
+/// Where a module or body is in its file, as `line:column-line:column` from its `{` to
+/// its `}`. Columns matter, as the item owning the braces may start on the same line.
+/// This must agree with `module_prefix` in `overrider`
+fn span_range(span: proc_macro2::Span) -> String {
+    let (start, end) = (span.start(), span.end());
+    format!("{}:{}-{}:{}", start.line, start.column, end.line, end.column)
+}
+
+/// Collects the items within some code, without going into the items themselves
+#[derive(Default)]
+struct NestedItems<'a>(Vec<&'a syn::Item>);
+
+impl<'a> Visit<'a> for NestedItems<'a> {
+    fn visit_item(&mut self, item: &'a syn::Item) {
+	self.0.push(item);
+    }
+}
+
+/// Function bodies get a namespace of their own, named after the function. Each
+/// version of an overriden or flagged function gets its own, as they may all be compiled
+fn body_name(attrs: &[syn::Attribute], name: &str) -> String {
+    match get_priority(attrs) {
+	Norm(priority) => format!("{}_{}_fn", name, priority),
+	Flagged(version, priority) => format!("{}_{}_{}_fn", name, version.name(), priority),
+	Final | Empty => format!("{}_fn", name),
+    }
+}
+
+/// Each version of an overriden module gets a namespace of its own,
+/// as only one of them will end up being compiled
+fn module_name(item: &ItemMod) -> String {
+    match get_priority(&item.attrs) {
+	Norm(priority) => format!("{}_{}", item.ident, priority),
+	_ => item.ident.to_string(),
+    }
+}
+
+/// Files brought in with `mod foo;` (including inside inline modules) by `file`,
+/// along with the prefix of each relative to `file`, and whether `#[cfg]` keeps it
+fn declared_modules(file: &path::Path, items: &[syn::Item]) -> Vec<(PathBuf, String, bool)> {
+    let file_dir = file.parent().unwrap_or_else(|| path::Path::new(""));
+    // `main.rs`, `lib.rs` and `mod.rs` keep their modules next to them,
+    // everything else in a directory of the same name
+    let dir = match file.file_stem().and_then(|stem| stem.to_str()) {
+	Some("main") | Some("lib") | Some("mod") | None => file_dir.to_path_buf(),
+	Some(stem) => file_dir.join(stem),
+    };
+    let mut found = Vec::new();
+    find_modules(items, file_dir, &dir, "", false, true, &mut found);
+    found
+}
+
+fn find_modules(items: &[syn::Item], file_dir: &path::Path, dir: &path::Path, prefix: &str,
+		inline: bool, enabled: bool, found: &mut Vec<(PathBuf, String, bool)>) {
+    for item in items {
+	if let syn::Item::Mod(item) = item {
+	    let prefix = format!("{}{}__", prefix, module_name(item));
+	    let enabled = enabled && cfg_enabled(&item.attrs);
+	    match &item.content {
+		Some((_, items)) =>
+		    find_modules(items, file_dir, &dir.join(item.ident.to_string()), &prefix, true,
+				 enabled, found),
+		None => {
+		    let file = match path_attr(&item.attrs) {
+			// #[path] is relative to the current file, unless within an inline module
+			Some(path) if !inline => file_dir.join(path),
+			Some(path) => dir.join(path),
+			None => {
+			    let flat = dir.join(format!("{}.rs", item.ident));
+			    if flat.exists() {
+				flat
+			    } else {
+				dir.join(item.ident.to_string()).join("mod.rs")
+			    }
+			},
+		    };
+		    found.push((file, prefix, enabled));
+		},
+	    }
+	}
+    }
+}
+
+/// The `foo.rs` in `#[path = "foo.rs"]`
+fn path_attr(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().filter(|attr| attr.path.is_ident("path")).find_map(|attr| {
+	match attr.parse_meta() {
+	    Ok(syn::Meta::NameValue(syn::MetaNameValue{lit: syn::Lit::Str(path), ..})) =>
+		Some(path.value()),
+	    _ => None,
+	}
+    })
+}
+
+/// Two flagged versions with the same priority are fine only if they can never both
+/// be picked, such as `mode = "fast"` and `mode = "safe"`. Otherwise there's no telling
+/// which should win, so the build stops
+fn check_exclusive(first: &Flagger, second: &Flagger, priority: u32) {
+    match (&first.version, &second.version) {
+	// the same flag or variable holding different values
+	(Version::Value{flag, ..}, Version::Value{flag: other, ..}) if flag == other => return,
+	(Version::FieldValue{field, value}, Version::FieldValue{field: other, value: other_value})
+	    if field == other && value != other_value => return,
+	(Version::Env{var, value: Some(_)}, Version::Env{var: other, value: Some(_)})
+	    if var == other => return,
+	_ => {},
+    }
+    // counts of the same flag are checked most to least
+    if let (Some((flag, first)), Some((other, second))) = (min_occurrences(first),
+							   min_occurrences(second)) {
+	if flag == other && first != second {
+	    return;
+	}
+    }
+    panic!("Flagged versions of `{}` for {} and {} both have priority {}, so it's unclear \
+	    which should win when both apply. Give one of them a higher priority",
+	   first.sig, first.version, second.version, priority);
+}
+
+/// The flag a `Flagger` counts, and how many times it must be given.
+/// Plain flags need to be given once
+fn min_occurrences(flagger: &Flagger) -> Option<(&str, u64)> {
+    match &flagger.version {
+	Version::Count{flag, min} => Some((flag, *min)),
+	Version::Flag{flag, invert: false} => Some((flag, 1)),
+	_ => None,
+    }
+}
+
+/// A flag pinned at build time by `OVERRIDER_BAKE_FLAGS`
+enum Baked {
+    Unset,
+    Set(Option<String>),
+}
+
+/// The flags listed in `OVERRIDER_BAKE_FLAGS`, as in `fast,!legacy,mode=safe`
+fn baked_flags() -> HashMap<String, Baked> {
+    println!("cargo:rerun-if-env-changed=OVERRIDER_BAKE_FLAGS");
+    std::env::var("OVERRIDER_BAKE_FLAGS").unwrap_or_default().split(',')
+	.map(str::trim)
+	.filter(|flag| !flag.is_empty())
+	.map(|flag| match (flag.strip_prefix('!'), flag.split_once('=')) {
+	    (Some(flag), _) => (flag.to_string(), Baked::Unset),
+	    (None, Some((flag, value))) => (flag.to_string(), Baked::Set(Some(value.to_string()))),
+	    (None, None) => (flag.to_string(), Baked::Set(None)),
+	})
+	.collect()
+}
+
+/// Whether a version applies, if the baked flags are enough to tell
+fn baked_holds(version: &Version, baked: &HashMap<String, Baked>) -> Option<bool> {
+    let set = |flag: &str| baked.get(flag).map(|baked| matches!(baked, Baked::Set(_)));
+    match version {
+	Version::Flag{flag, invert} => set(flag).map(|set| set != *invert),
+	Version::Value{flag, value} => match baked.get(flag)? {
+	    Baked::Set(Some(baked)) => Some(baked == value),
+	    _ => Some(false),
+	},
+	Version::Count{flag, min} => match baked.get(flag)? {
+	    Baked::Unset => Some(false),
+	    // `v=2` is as if given twice. Any other value is given once
+	    Baked::Set(Some(count)) => Some(count.parse::<u64>().unwrap_or(1) >= *min),
+	    Baked::Set(None) => Some(1 >= *min),
+	},
+	Version::When(when) => When::parse_str(when).ok()?.eval(&set),
+	// CPU features, environment variables and parsed fields are only known at runtime
+	Version::Cpu(_) | Version::Env{..} | Version::Field{..} | Version::FieldValue{..} => None,
+    }
+}
+
+/// What's left of a `when` to check at runtime, if some of its flags are baked
+fn baked_when(when: &str, baked: &HashMap<String, Baked>) -> Option<Version> {
+    let when = When::parse_str(when).ok()?;
+    if when.flags().iter().all(|flag| !baked.contains_key(*flag)) {
+	return None;
+    }
+    let set = |flag: &str| baked.get(flag).map(|baked| matches!(baked, Baked::Set(_)));
+    Some(Version::When(when.bake(&set).to_string()))
 }
 
 /// Scans a vector of files, constructing and handling the Cargo config flags that interface
@@ -99,143 +546,259 @@ struct Flagger {
 /// `watch_files` takes a single arguement: `file_names`. This is a vector of str
 /// references, who point to file strings.  
 /// **Globbing is supported**
+///
+/// ## Modules
+/// Items are told apart by the module they're in, so `fn init()` in `mod net` and
+/// `fn init()` in `mod db` don't override each other. A file brought in by `mod foo;`
+/// from another watched file is known to be module `foo`. Every other file is taken
+/// to be the root of a crate.
+///
+/// ## cfg
+/// Items with a `#[cfg(...)]` that rules them out (going by the features and target
+/// Cargo passes to build scripts) are skipped, along with everything inside of them.
+/// `test` and `doc` can't be known from a build script, so are taken to be enabled, and
+/// can't be on an overriden or flagged item itself. Put such items in a module behind them.
+///
+/// ## Baking flags
+/// Flags can be pinned for a whole build by listing them in the `OVERRIDER_BAKE_FLAGS`
+/// environment variable, as in `OVERRIDER_BAKE_FLAGS="fast,!legacy,mode=safe"`: `fast` is
+/// set (once), `legacy` isn't, and `mode` holds `safe`. A flag given several times, as in
+/// `-vv`, is baked with its count, as in `v=2`. When that's enough to tell which
+/// version of an item wins, the winner is compiled in place of the `#[default]`, leaving no
+/// dispatcher or other versions behind. Flags that aren't listed are still read at runtime,
+/// as are CPU features and environment variables.
+///
+/// ## Command line
+/// Every flag read by `#[override_flag]` is listed in `OUT_DIR/overrider_flags.rs`, as
+/// a `const OVERRIDER_FLAGS: &[overrider_runtime::Flag]`. Each flag's help is the first
+/// paragraph of the doc comment on the first item flagged by it. See
+/// `overrider_runtime::Flag` for turning them into `clap` arguments.
+///
+/// Each flag also records where it's read, so flags declared by hand can be checked
+/// against the list with `overrider_runtime::check_flags`, which names any misspelled
+/// ones by file and line.
 pub fn watch_files(file_names: Vec<&str>) {
 
-    // find all overrides in files
-    let mut overrides: Vec<Override> = Vec::new();
-    let mut finals:    Vec<String>   = Vec::new();
-    let mut flags:     Vec<Flagger>  = Vec::new();
+    // read in every file
+    let mut files: Vec<(PathBuf, syn::File)> = Vec::new();
     for file_name in file_names.into_iter()
-	.map(|g| glob(g).expect(&format!("Failed to read glob pattern '{}'", g))).flatten() {
+	.flat_map(|g| glob(g).unwrap_or_else(|_| panic!("Failed to read glob pattern '{}'", g))) {
 	    let file_name = match file_name {
 		Ok(file_name) => file_name,
 		Err(err) => panic!("Glob pattern resolution failed: {}", err),
 	    };
-	    
-	    let mut file = File::open(&file_name).expect(&format!("Unable to open file '{}'", file_name.display()));
-	    let mut src = String::new(); 
-	    file.read_to_string(&mut src).expect(&format!("Unable to read file '{}'", file_name.display()));
-
-	    let parsed = match syn::parse_file(&src) {
-		Ok(items) => items,
-		Err(_) => return, // There's a compiler error. Let rustc take care of it
+	    // Cargo only watches what it's told to once anything is named, as
+	    // OVERRIDER_BAKE_FLAGS is
+	    println!("cargo:rerun-if-changed={}", file_name.display());
+	    let parsed = match read_file(&file_name) {
+		Some(items) => items,
+		None => { // There's a compiler error. Let rustc take care of it
+		    write_flags(&[]); // without leaving an include! dangling
+		    return;
+		},
 	    };
-	    
-	    for item in parsed.items {
-		match item { // step over everything in the file
-		    syn::Item::Fn(func) => {
-			match get_priority(&func.attrs) {
-			    Norm(priority) =>
-				overrides.push(Override{
-				    sig: format!("func_{}",func.sig.ident),
-				    priority,
-				}),
-			    Flag(flag, priority, invert) => {
-				flags.push(Flagger{
-				    sig: format!("func_{}",func.sig.ident),
-				    flag: if invert {
-					format!("i_{}", flag)
-				    } else {
-					format!("_{}", flag)
-				    },
-				    priority,
-				})}
-			    ,
-			    Final => finals.push(format!("func_{}", func.sig.ident)),
-			    Empty => {},
-			}
-		    },
-		    syn::Item::Impl(impl_block) => {
-			match get_priority(&impl_block.attrs) {
-			    Flag(flag, priority, invert) => {
-				let self_type = match impl_block.self_ty.as_ref() { // The `Dummy` in `impl Dummy {}`
-				    Path(path) => path,
-				    _ => continue,
-				}.path.segments[0].ident.to_string();
-				
-				for item in impl_block.items {
-				    match item {
-					Method(method) =>
-					    flags.push(Flagger{
-						sig: format!("method_{}_{}",
-							     self_type,
-							     &method.sig.ident),
-						flag: if invert {
-						    format!("i_{}", flag)
-						} else {
-						    format!("_{}", flag)
-						},
-						priority,
-					    }),
-					Const(constant) =>
-					    flags.push(Flagger{
-						sig: format!("implconst_{}_{}",
-							     self_type,
-							     &constant.ident),
-						flag: if invert {
-						    format!("i_{}", flag)
-						} else {
-						    format!("_{}", flag)
-						},
-						priority,
-					    }),
-					_ => continue,
-				    }
-				}
-			    },
-			    Norm(priority) => {
-				let self_type = match impl_block.self_ty.as_ref() { // The `Dummy` in `impl Dummy {}`
-				    Path(path) => path,
-				    _ => continue,
-				}.path.segments[0].ident.to_string();
-				
-				for item in impl_block.items {
-				    match item {
-					Method(method) =>
-					    overrides.push(Override{
-						sig: format!("method_{}_{}",
-							     self_type,
-							     &method.sig.ident),
-						priority,
-					    }),
-					Const(constant) =>
-					    overrides.push(Override{
-						sig: format!("implconst_{}_{}",
-							     self_type,
-							     &constant.ident),
-						priority,
-					    }),
-					_ => continue,
-				    }
-				}
-			    },
-			    Final => {
-				let self_type = match impl_block.self_ty.as_ref() { // The `Dummy` in `impl Dummy {}`
-				    Path(path) => path,
-				    _ => continue,
-				}.path.segments[0].ident.to_string();
-				
-				for item in impl_block.items {
-				    match item {
-					Method(method) => 
-					    finals.push(format!("method_{}_{}",
-								self_type,
-								&method.sig.ident)),
-					Const(constant) =>
-					    finals.push(format!("implconst_{}_{}",
-								self_type,
-								&constant.ident)),
-					_ => continue,
-				    }
-				}
-			    },
-			    Empty => {},
-			}
-		    },
-		    _ => {} // can't parse everything yet
+	    files.push((file_name.canonicalize().unwrap_or(file_name), parsed));
+	}
+    watch(files, None);
+}
+
+/// Scans every file in the crate being built, constructing and handling the Cargo config
+/// flags that interface with `overrider`.
+///
+/// Starting from `src/main.rs` and `src/lib.rs`, every `mod` declaration is followed
+/// (`#[path]` included), so there's no list of files to keep up to date. Cargo is told
+/// to rerun the build script when any of these files change.
+///
+/// The binary and the library are separate crates, so items in one are overriden apart
+/// from those in the other, even where they share a name.
+///
+/// ## A word of warning
+/// Just like `watch_files`, **only call `watch_crate` once**, and don't call both.
+///
+/// ## Syntax
+/// `watch_crate` takes no arguments:
+/// ```no_run
+/// fn main() {
+///     overrider_build::watch_crate();
+/// }
+/// ```
+pub fn watch_crate() {
+    let src = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR")
+			    .expect("CARGO_MANIFEST_DIR not set. Is this a build script?"))
+	.join("src");
+    let mut pending: Vec<PathBuf> = ["main.rs", "lib.rs"].iter()
+	.map(|root| src.join(root)).filter(|root| root.exists()).collect();
+    let main = pending.first().filter(|_| pending.len() == 2) // a library too
+	.map(|main| main.canonicalize().unwrap_or_else(|_| main.clone()));
+
+    // follow mod declarations through the crate
+    let mut files: Vec<(PathBuf, syn::File)> = Vec::new();
+    while let Some(file_name) = pending.pop() {
+	let file_name = file_name.canonicalize().unwrap_or(file_name);
+	if !file_name.exists() || files.iter().any(|(seen, _)| seen == &file_name) {
+	    continue; // missing files are for rustc to complain about
+	}
+	println!("cargo:rerun-if-changed={}", file_name.display());
+	let parsed = match read_file(&file_name) {
+	    Some(items) => items,
+	    None => { // There's a compiler error. Let rustc take care of it
+		write_flags(&[]); // without leaving an include! dangling
+		return;
+	    },
+	};
+	pending.extend(declared_modules(&file_name, &parsed.items).into_iter()
+		       .filter(|(_, _, enabled)| *enabled)
+		       .map(|(module, _, _)| module));
+	files.push((file_name, parsed));
+    }
+    watch(files, main);
+}
+
+/// `file` from the crate being built, if it's in there
+fn relative_path(file: &path::Path) -> String {
+    let manifest_dir = std::env::var_os("CARGO_MANIFEST_DIR").map(PathBuf::from)
+	.and_then(|dir| dir.canonicalize().ok());
+    manifest_dir.as_ref().and_then(|dir| file.strip_prefix(dir).ok()).unwrap_or(file)
+	.display().to_string()
+}
+
+/// The first paragraph of the doc comment in `attrs`, on one line
+fn doc_summary(attrs: &[syn::Attribute]) -> String {
+    let lines = attrs.iter().filter(|attr| attr.path.is_ident("doc")).filter_map(|attr| {
+	match attr.parse_meta() {
+	    Ok(syn::Meta::NameValue(syn::MetaNameValue{lit: syn::Lit::Str(doc), ..})) =>
+		Some(doc.value().trim().to_string()),
+	    _ => None,
+	}
+    });
+    lines.skip_while(|line| line.is_empty()).take_while(|line| !line.is_empty())
+	.collect::<Vec<_>>().join(" ")
+}
+
+/// A flag as it goes on the command line
+#[derive(Default)]
+struct CliFlag {
+    name: String,
+    help: String,
+    takes_value: bool,
+    multiple: bool,
+    locations: Vec<String>,
+}
+
+/// Lists every flag read by `#[override_flag]` in `OUT_DIR/overrider_flags.rs`, to build
+/// a command line parser from. Help comes from the doc comment of the first item
+/// flagged by each flag
+fn write_flags(flags: &[Flagger]) {
+    let out_dir = match std::env::var_os("OUT_DIR") {
+	Some(out_dir) => PathBuf::from(out_dir),
+	None => return, // not run by Cargo
+    };
+    let mut cli: Vec<CliFlag> = Vec::new();
+    // each flag once, with the first help found and everywhere it's used
+    fn add<'a>(cli: &'a mut Vec<CliFlag>, name: &str, doc: &str, location: &str)
+	       -> &'a mut CliFlag {
+	let position = match cli.iter().position(|flag| flag.name == name) {
+	    Some(position) => position,
+	    None => {
+		cli.push(CliFlag{name: name.to_string(), ..Default::default()});
+		cli.len() - 1
+	    },
+	};
+	let flag = &mut cli[position];
+	if flag.help.is_empty() {
+	    flag.help = doc.to_string();
+	}
+	if !flag.locations.iter().any(|known| known == location) {
+	    flag.locations.push(location.to_string());
+	}
+	flag
+    }
+    for flagger in flags {
+	match &flagger.version {
+	    Version::Flag{flag, ..} => {
+		add(&mut cli, flag, &flagger.doc, &flagger.location);
+	    },
+	    Version::Value{flag, ..} =>
+		add(&mut cli, flag, &flagger.doc, &flagger.location).takes_value = true,
+	    Version::Count{flag, min} =>
+		add(&mut cli, flag, &flagger.doc, &flagger.location).multiple |= *min > 1,
+	    Version::When(when) => if let Ok(when) = When::parse_str(when) {
+		for flag in when.flags() {
+		    // the doc is about all of them, not any one flag
+		    add(&mut cli, flag, "", &flagger.location);
+		}
+	    },
+	    // fields are declared by the parser struct itself
+	    Version::Cpu(_) | Version::Env{..} | Version::Field{..} | Version::FieldValue{..} => {},
+	}
+    }
+
+    let mut out = String::from("// Every flag read by #[override_flag], found by overrider_build\n\
+				#[allow(dead_code)]\n\
+				const OVERRIDER_FLAGS: &[::overrider_runtime::Flag] = &[\n");
+    for flag in cli {
+	out.push_str(&format!("    ::overrider_runtime::Flag {{ name: {:?}, help: {:?}, \
+			       takes_value: {}, multiple: {}, locations: &{:?} }},\n",
+			      flag.name, flag.help, flag.takes_value, flag.multiple,
+			      flag.locations));
+    }
+    out.push_str("];\n");
+    std::fs::write(out_dir.join("overrider_flags.rs"), out)
+	.unwrap_or_else(|error| panic!("Unable to write overrider_flags.rs: {}", error));
+}
+
+/// `None` if the file doesn't parse
+fn read_file(file_name: &path::Path) -> Option<syn::File> {
+    let mut file = File::open(file_name)
+	.unwrap_or_else(|_| panic!("Unable to open file '{}'", file_name.display()));
+    let mut src = String::new(); 
+    file.read_to_string(&mut src)
+	.unwrap_or_else(|_| panic!("Unable to read file '{}'", file_name.display()));
+    syn::parse_file(&src).ok()
+}
+
+/// `main` is the root of a binary built alongside a library, when there's one to tell apart
+fn watch(files: Vec<(PathBuf, syn::File)>, main: Option<PathBuf>) {
+    // work out which module each file is
+    let declared = files.iter().map(|(file_name, parsed)| declared_modules(file_name, &parsed.items))
+	.collect::<Vec<_>>();
+    // Everything in the binary sits under a prefix of its own, which can't be mistaken
+    // for a module's as identifiers don't start with a digit
+    let mut prefixes = files.iter()
+	.map(|(file_name, _)| match &main {
+	    Some(main) if main == file_name => "0main__".to_string(),
+	    _ => String::new(),
+	})
+	.collect::<Vec<_>>();
+    let mut enabled = files.iter().map(|(_, parsed)| cfg_enabled(&parsed.attrs))
+	.collect::<Vec<_>>();
+    for _ in 0..files.len() { // enough passes to get through the deepest module
+	for (parent, modules) in declared.iter().enumerate() {
+	    for (module, prefix, module_enabled) in modules {
+		let module = module.canonicalize().unwrap_or_else(|_| module.clone());
+		if let Some(child) = files.iter().position(|(file_name, _)| file_name == &module) {
+		    prefixes[child] = format!("{}{}", prefixes[parent], prefix);
+		    enabled[child] = enabled[child] && enabled[parent] && *module_enabled;
 		}
 	    }
 	}
+    }
+
+    // find all overrides in files
+    let mut scan = Scan::default();
+    for (((file_name, parsed), prefix), enabled) in files.iter().zip(prefixes).zip(enabled) {
+	if !enabled {
+	    continue; // won't be compiled, so can't win
+	}
+	let mut modules = prefix.clone();
+	scan.file = relative_path(file_name);
+	scan.items(&parsed.items, &prefix, &mut modules);
+	if !modules.is_empty() {
+	    println!("cargo:rustc-env=__override_modules_{}={}", file_id(file_name), modules);
+	}
+    }
+    let Scan{overrides, finals, flags, ..} = scan;
+    write_flags(&flags);
 
     // group them into like targets
     let mut override_chains: Vec<Vec<Override>> = Vec::new();
@@ -257,8 +820,10 @@ pub fn watch_files(file_names: Vec<&str>) {
 	    }
 	}
 	for (i, overrider) in chain.iter().enumerate(){
+	    let cfg = format!("__override_priority_{}_{}", overrider.priority, overrider.sig);
+	    println!("cargo:rustc-check-cfg=cfg({})", cfg);
 	    if i_of_max != i {
-		println!("cargo:rustc-cfg=__override_priority_{}_{}", overrider.priority, overrider.sig);
+		println!("cargo:rustc-cfg={}", cfg);
 	    }
 	};
     }
@@ -271,11 +836,12 @@ pub fn watch_files(file_names: Vec<&str>) {
     }
 
     // now for flags. This will look familiar
+    let baked = baked_flags();
     let mut flag_chains: Vec<Vec<Vec<Flagger>>> = Vec::new();
     // [[[for each priority] for each --flag] for each item]
     for flag in flags {
 	if let Some(item_found) = flag_chains.iter().position(|chain| chain[0][0].sig == flag.sig) {
-	    if let Some(flag_found) = flag_chains[item_found].iter().position(|flag_pack| flag_pack[0].flag == flag.flag) {
+	    if let Some(flag_found) = flag_chains[item_found].iter().position(|flag_pack| flag_pack[0].version == flag.version) {
 		flag_chains[item_found][flag_found].push(flag);
 	    } else {
 		flag_chains[item_found].push(vec![flag]);
@@ -285,20 +851,187 @@ pub fn watch_files(file_names: Vec<&str>) {
 	}
     }
     
-    for flag_chain in flag_chains.into_iter() {
-	let cargoflag = format!("__override_acceptflags_{}", flag_chain[0][0].sig);
-	let item_flags = flag_chain.iter().map(|e| e[0].flag.clone())
-	    .collect::<Vec<String>>().join(" "); // TODO: error check for spaces in flag
-	println!("cargo:rustc-env={}={}", cargoflag, item_flags);
-	
-	for flag in flag_chain.into_iter() { // TODO: combine with iter above
+    for mut flag_chain in flag_chains.into_iter() {
+	// Whichever flag has the highest priority is checked first, and so wins when more than
+	// one is set. The winning priority of each flag is what counts
+	let winning = |flag: &Vec<Flagger>| flag.iter().map(|p| p.priority).max().unwrap_or(0);
+	// and for the same priority, the most occurrences of a flag
+	flag_chain.sort_by_key(|flag| (std::cmp::Reverse(winning(flag)),
+				       std::cmp::Reverse(min_occurrences(&flag[0]).map(|(_, n)| n))));
+	for pair in flag_chain.windows(2) {
+	    if winning(&pair[0]) == winning(&pair[1]) {
+		check_exclusive(&pair[0][0], &pair[1][0], winning(&pair[0]));
+	    }
+	}
+
+	// Flags pinned by OVERRIDER_BAKE_FLAGS are settled here. Versions that can't apply are
+	// dropped, and the first version that must apply is as far as the chain goes
+	let holds = flag_chain.iter().map(|flag| baked_holds(&flag[0].version, &baked))
+	    .collect::<Vec<_>>();
+	let settled = holds.iter().position(|holds| *holds == Some(true));
+	let kept = |i: usize| settled.is_none_or(|settled| i <= settled) && holds[i] != Some(false);
+	let sig = flag_chain[0][0].sig.clone();
+	let item_flags = flag_chain.iter().enumerate().filter(|(i, _)| kept(*i))
+	    .map(|(i, e)| match &e[0].version {
+		_ if Some(i) == settled =>
+		    format!("b_{}", e[0].version.name()), // always taken once reached
+		// a `when` only partly pinned is followed by what's left of it to check
+		Version::When(when) => match baked_when(when, &baked) {
+		    Some(rest) => format!("{}={}", e[0].version.name(), rest.name()),
+		    None => e[0].version.name(),
+		},
+		version => version.name(),
+	    })
+	    .collect::<Vec<String>>();
+	match settled {
+	    Some(settled) if item_flags.len() == 1 => {
+		// nothing's left to check at runtime, so the version takes the place of the default
+		println!("cargo:rustc-env=__override_baked_{}={}", sig,
+			 flag_chain[settled][0].version.name());
+		for overrider in override_chains.iter().flatten().filter(|overrider| overrider.sig == sig) {
+		    println!("cargo:rustc-cfg=__override_priority_{}_{}", overrider.priority, sig);
+		}
+	    },
+	    _ if item_flags.is_empty() => // the default is all that's left
+		println!("cargo:rustc-env=__override_baked_{}=", sig),
+	    _ => println!("cargo:rustc-env=__override_acceptflags_{}={}", sig, item_flags.join(" ")),
+	}
+
+	for (i, flag) in flag_chain.into_iter().enumerate() { // TODO: combine with iter above
+	    let kept = kept(i);
 	    let (i_of_max, _) = flag.iter().enumerate()
 		.max_by_key(|x| x.1.priority).unwrap();
 	    for (i, p) in flag.into_iter().enumerate() {
-		if i_of_max != i { // TODO: chuck recursive parse in override
-		    println!("cargo:rustc-cfg=__override_priority_{}_flag_{}_{}", p.priority, p.flag, p.sig);
+		let cfg = format!("__override_priority_{}_flag_{}_{}", p.priority, p.version.name(),
+				  p.sig);
+		println!("cargo:rustc-check-cfg=cfg({})", cfg);
+		if i_of_max != i || !kept { // TODO: chuck recursive parse in override
+		    println!("cargo:rustc-cfg={}", cfg);
 		}
 	    }
 	}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The attributes of `item`
+    fn attrs(item: syn::ItemFn) -> Vec<syn::Attribute> {
+	item.attrs
+    }
+
+    // Each test reads variables no other test sets, as they run side by side
+
+    #[test]
+    fn features() {
+	std::env::set_var("CARGO_FEATURE_TEST_FEATURES_ON", "1");
+	assert!(cfg_enabled(&attrs(syn::parse_quote! {
+	    #[cfg(feature = "test-features-on")] fn f() {}
+	})));
+	assert!(!cfg_enabled(&attrs(syn::parse_quote! {
+	    #[cfg(feature = "test-features-off")] fn f() {}
+	})));
+	assert!(cfg_enabled(&attrs(syn::parse_quote! { fn f() {} })));
+    }
+
+    #[test]
+    fn targets() {
+	std::env::set_var("CARGO_CFG_TEST_TARGET_OS", "linux");
+	std::env::set_var("CARGO_CFG_TEST_TARGET_FAMILY", "unix,wasm");
+	std::env::set_var("CARGO_CFG_TEST_UNIX", "");
+	let enabled = |item| cfg_enabled(&attrs(item));
+	assert!(enabled(syn::parse_quote! { #[cfg(test_target_os = "linux")] fn f() {} }));
+	assert!(!enabled(syn::parse_quote! { #[cfg(test_target_os = "windows")] fn f() {} }));
+	assert!(enabled(syn::parse_quote! { #[cfg(test_target_family = "wasm")] fn f() {} }));
+	assert!(enabled(syn::parse_quote! { #[cfg(test_unix)] fn f() {} }));
+	assert!(!enabled(syn::parse_quote! { #[cfg(test_windows)] fn f() {} }));
+    }
+
+    #[test]
+    fn combinations() {
+	std::env::set_var("CARGO_CFG_TEST_COMBINATIONS_ON", "");
+	let eval = |predicate: syn::NestedMeta| eval_cfg(&predicate);
+	assert_eq!(eval(syn::parse_quote!(not(test_combinations_on))), Some(false));
+	assert_eq!(eval(syn::parse_quote!(not(test_combinations_off))), Some(true));
+	assert_eq!(eval(syn::parse_quote!(all(test_combinations_on, test_combinations_off))),
+		   Some(false));
+	assert_eq!(eval(syn::parse_quote!(any(test_combinations_on, test_combinations_off))),
+		   Some(true));
+	assert_eq!(eval(syn::parse_quote!(all())), Some(true));
+	assert_eq!(eval(syn::parse_quote!(any())), Some(false));
+	// what can't be told only matters when it could change the answer
+	assert_eq!(eval(syn::parse_quote!(all(test_combinations_on, doc))), None);
+	assert_eq!(eval(syn::parse_quote!(all(test_combinations_off, doc))), Some(false));
+	assert_eq!(eval(syn::parse_quote!(any(test_combinations_on, doc))), Some(true));
+	assert_eq!(eval(syn::parse_quote!(not(any(test_combinations_off, test)))), None);
+	assert!(cfg_enabled(&attrs(syn::parse_quote! { #[cfg(unknown(x))] fn f() {} })));
+    }
+
+    #[test]
+    fn baked_counts() {
+	let baked = vec![("v".to_string(), Baked::Set(Some("2".to_string()))),
+			 ("d".to_string(), Baked::Set(None)),
+			 ("q".to_string(), Baked::Unset)]
+	    .into_iter().collect::<HashMap<_, _>>();
+	let holds = |flag: &str, min| baked_holds(&Version::Count{flag: flag.into(), min}, &baked);
+	assert_eq!((holds("v", 1), holds("v", 2), holds("v", 3)), (Some(true), Some(true), Some(false)));
+	assert_eq!((holds("d", 1), holds("d", 2)), (Some(true), Some(false)));
+	assert_eq!((holds("q", 1), holds("x", 1)), (Some(false), None));
+    }
+
+    #[test]
+    fn partly_baked_when() {
+	let baked = vec![("a".to_string(), Baked::Set(None)), ("b".to_string(), Baked::Unset)]
+	    .into_iter().collect::<HashMap<_, _>>();
+	let rest = |when: &str| baked_when(when, &baked).map(|version| match version {
+	    Version::When(when) => when,
+	    version => panic!("{} isn't a `when`", version),
+	});
+	assert_eq!(rest("a && c"), Some(r#"all("c")"#.to_string()));
+	assert_eq!(rest("b || !c"), Some(r#"any(not("c"))"#.to_string()));
+	assert_eq!(rest("any(all(a, c), b, d)"), Some(r#"any(all("c"), "d")"#.to_string()));
+	assert_eq!(rest("c && d"), None); // nothing baked to leave out
+	assert_eq!(When::parse_str(&rest("a && !b && c").unwrap()).unwrap(),
+		   When::All(vec![When::Flag("c".to_string())]));
+    }
+
+    #[test]
+    fn build_cfgs_only_matter_on_overrides() {
+	let plain = attrs(syn::parse_quote! { #[cfg(test)] fn f() {} });
+	check_build_cfgs(&plain, &plain, "src/main.rs");
+	let outer = attrs(syn::parse_quote! { #[default] fn f() {} });
+	let inner = attrs(syn::parse_quote! { #[cfg(feature = "x")] fn f() {} });
+	check_build_cfgs(&inner, &outer, "src/main.rs");
+    }
+
+    #[test]
+    #[should_panic(expected = "src/main.rs:1: #[cfg(test)] can't be on an overriden")]
+    fn test_cfg_on_override() {
+	let attrs = attrs(syn::parse_quote! { #[cfg(test)] #[override_default] fn f() {} });
+	check_build_cfgs(&attrs, &attrs, "src/main.rs");
+    }
+
+    #[test]
+    #[should_panic(expected = "src/lib.rs:1: #[override_cfg(test)] can't be on an overriden")]
+    fn test_override_cfg() {
+	let attrs = attrs(syn::parse_quote! { #[override_cfg(test)] fn f() {} });
+	check_build_cfgs(&attrs, &attrs, "src/lib.rs");
+    }
+
+    #[test]
+    #[should_panic(expected = "src/lib.rs:1: #[override_cfg(priority = 2")]
+    fn nested_doc_override_cfg() {
+	let attrs = attrs(syn::parse_quote! { #[override_cfg(priority = 2, not(doc))] fn f() {} });
+	check_build_cfgs(&attrs, &attrs, "src/lib.rs");
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be on an overriden or flagged item")]
+    fn nested_doc_cfg_on_flagged_method() {
+	let method = attrs(syn::parse_quote! { #[cfg(not(any(doc, unix)))] fn f() {} });
+	let block = attrs(syn::parse_quote! { #[override_flag(flag = a)] fn f() {} });
+	check_build_cfgs(&method, &block, "src/lib.rs");
+    }
+}
@@ -0,0 +1,728 @@
+//! `overrider_common` holds what `overrider` and `overrider_build` need to agree on:
+//! the arguments of `#[override_flag]`, `#[override_env]` and `#[override_cpu]`, the
+//! names the versions they make are passed around by, and the names items and files are
+//! keyed by. You're probably looking for
+//! [`overrider`](https://docs.rs/overrider).
+
+use proc_macro2::Span;
+use quote::ToTokens;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Ident, ItemImpl, LitBool, LitInt, LitStr, PathArguments, Token, UseTree};
+
+/// What picks a flagged version of an item
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Version {
+    /// `flag = name`, or with `invert = true`, when it isn't set
+    Flag { flag: String, invert: bool },
+    /// `flag = name, value = "..."`
+    Value { flag: String, value: String },
+    /// `flag = name, min_occurrences = N`
+    Count { flag: String, min: u64 },
+    /// `when = "..."`, the expression as it was written
+    When(String),
+    /// `#[override_cpu(feature = "...")]`
+    Cpu(String),
+    /// `#[override_env(var = "...", value = "...")]`, or any value with `set = true`
+    Env { var: String, value: Option<String> },
+    /// `field = name`, a `bool` field of the parser struct, or with `invert = true`,
+    /// when it's false
+    Field { field: String, invert: bool },
+    /// `field = name, value = "Mode::Fast"`, when the field matches the pattern
+    FieldValue { field: String, value: String },
+}
+
+impl Version {
+    /// The name of this version, which goes into the identifiers and cfgs of it.
+    /// Flags and values can hold anything, so are written out in hex
+    pub fn name(&self) -> String {
+	match self {
+	    Version::Flag{flag, invert: false} => format!("f_{}", hex(flag)),
+	    Version::Flag{flag, invert: true} => format!("i_{}", hex(flag)),
+	    Version::Value{flag, value} => format!("v_{}_{}", hex(flag), hex(value)),
+	    Version::Count{flag, min} => format!("n_{}_{}", hex(flag), min),
+	    Version::When(when) => format!("w_{}", hex(when)),
+	    Version::Cpu(feature) => format!("c_{}", hex(feature)),
+	    Version::Env{var, value: None} => format!("e_{}", hex(var)),
+	    Version::Env{var, value: Some(value)} => format!("e_{}_{}", hex(var), hex(value)),
+	    Version::Field{field, invert: false} => format!("d_{}", hex(field)),
+	    Version::Field{field, invert: true} => format!("u_{}", hex(field)),
+	    Version::FieldValue{field, value} => format!("m_{}_{}", hex(field), hex(value)),
+	}
+    }
+
+    /// The version a `name` came from
+    pub fn from_name(name: &str) -> Option<Version> {
+	let (kind, rest) = name.split_once('_')?;
+	let pair = || rest.split_once('_');
+	Some(match kind {
+	    "f" => Version::Flag{flag: unhex(rest)?, invert: false},
+	    "i" => Version::Flag{flag: unhex(rest)?, invert: true},
+	    "v" => Version::Value{flag: unhex(pair()?.0)?, value: unhex(pair()?.1)?},
+	    "n" => Version::Count{flag: unhex(pair()?.0)?, min: pair()?.1.parse().ok()?},
+	    "w" => Version::When(unhex(rest)?),
+	    "c" => Version::Cpu(unhex(rest)?),
+	    "e" => match pair() {
+		Some((var, value)) => Version::Env{var: unhex(var)?, value: Some(unhex(value)?)},
+		None => Version::Env{var: unhex(rest)?, value: None},
+	    },
+	    "d" => Version::Field{field: unhex(rest)?, invert: false},
+	    "u" => Version::Field{field: unhex(rest)?, invert: true},
+	    "m" => Version::FieldValue{field: unhex(pair()?.0)?, value: unhex(pair()?.1)?},
+	    _ => return None,
+	})
+    }
+}
+
+/// How a version was written, for messages
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	match self {
+	    Version::Flag{flag, invert: false} => write!(f, "`flag = {:?}`", flag),
+	    Version::Flag{flag, invert: true} => write!(f, "`flag = {:?}, invert = true`", flag),
+	    Version::Value{flag, value} => write!(f, "`flag = {:?}, value = {:?}`", flag, value),
+	    Version::Count{flag, min} =>
+		write!(f, "`flag = {:?}, min_occurrences = {}`", flag, min),
+	    Version::When(when) => write!(f, "`when = {:?}`", when),
+	    Version::Cpu(feature) => write!(f, "`feature = {:?}`", feature),
+	    Version::Env{var, value: None} => write!(f, "`var = {:?}, set = true`", var),
+	    Version::Env{var, value: Some(value)} => write!(f, "`var = {:?}, value = {:?}`", var, value),
+	    Version::Field{field, invert: false} => write!(f, "`field = {}`", field),
+	    Version::Field{field, invert: true} => write!(f, "`field = {}, invert = true`", field),
+	    Version::FieldValue{field, value} =>
+		write!(f, "`field = {}, value = {:?}`", field, value),
+	}
+    }
+}
+
+fn hex(s: &str) -> String {
+    s.bytes().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(hex: &str) -> Option<String> {
+    let bytes = (0..hex.len()).step_by(2)
+	.map(|i| hex.get(i..i+2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+	.collect::<Option<Vec<_>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+/// A `when` expression, such as `a && !b` or `any(a, not(b))`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum When {
+    Flag(String),
+    Not(Box<When>),
+    All(Vec<When>),
+    Any(Vec<When>),
+}
+
+impl When {
+    /// Errors point at `lit`, as there's no pointing inside of it
+    pub fn parse(lit: &LitStr) -> syn::Result<When> {
+	When::parse_str(&lit.value()).map_err(|error| syn::Error::new(lit.span(), error))
+    }
+
+    pub fn parse_str(when: &str) -> syn::Result<When> {
+	When::from_expr(&syn::parse_str(when)?)
+    }
+
+    fn from_expr(expr: &syn::Expr) -> syn::Result<When> {
+	let expected = "Expected a flag name, `&&`, `||`, `!`, `any(..)`, `all(..)` or `not(..)`";
+	Ok(match expr {
+	    syn::Expr::Path(path) => match path.path.get_ident() {
+		Some(flag) => When::Flag(flag.to_string()),
+		None => return Err(syn::Error::new_spanned(path, expected)),
+	    },
+	    syn::Expr::Lit(syn::ExprLit{lit: syn::Lit::Str(flag), ..}) => When::Flag(flag.value()),
+	    syn::Expr::Paren(paren) => When::from_expr(&paren.expr)?,
+	    syn::Expr::Unary(syn::ExprUnary{op: syn::UnOp::Not(_), expr, ..}) =>
+		When::Not(Box::new(When::from_expr(expr)?)),
+	    syn::Expr::Binary(binary) => {
+		let both = vec![When::from_expr(&binary.left)?, When::from_expr(&binary.right)?];
+		match binary.op {
+		    syn::BinOp::And(_) => When::All(both),
+		    syn::BinOp::Or(_) => When::Any(both),
+		    op => return Err(syn::Error::new_spanned(op, "Expected `&&` or `||`")),
+		}
+	    },
+	    syn::Expr::Call(call) => {
+		let args = call.args.iter().map(When::from_expr).collect::<syn::Result<Vec<_>>>()?;
+		match call.func.as_ref() {
+		    syn::Expr::Path(path) if path.path.is_ident("any") => When::Any(args),
+		    syn::Expr::Path(path) if path.path.is_ident("all") => When::All(args),
+		    syn::Expr::Path(path) if path.path.is_ident("not") && args.len() == 1 =>
+			When::Not(Box::new(args.into_iter().next().unwrap())),
+		    func => return Err(syn::Error::new_spanned(
+			func, "Expected `any(..)`, `all(..)` or `not(..)` with one argument")),
+		}
+	    },
+	    expr => return Err(syn::Error::new_spanned(expr, expected)),
+	})
+    }
+
+    /// Every flag it reads, in order, each once
+    pub fn flags(&self) -> Vec<&str> {
+	let mut flags = Vec::new();
+	self.collect_flags(&mut flags);
+	flags
+    }
+
+    fn collect_flags<'a>(&'a self, flags: &mut Vec<&'a str>) {
+	match self {
+	    When::Flag(flag) if !flags.contains(&flag.as_str()) => flags.push(flag),
+	    When::Flag(_) => {},
+	    When::Not(when) => when.collect_flags(flags),
+	    When::All(whens) | When::Any(whens) => for when in whens {
+		when.collect_flags(flags);
+	    },
+	}
+    }
+
+    /// Whether it holds, given whether each flag is set. A flag that can't be told
+    /// (`None`) only leaves the answer open if it could change it
+    pub fn eval(&self, set: &impl Fn(&str) -> Option<bool>) -> Option<bool> {
+	match self {
+	    When::Flag(flag) => set(flag),
+	    When::Not(when) => when.eval(set).map(|holds| !holds),
+	    When::All(whens) => {
+		let values = whens.iter().map(|when| when.eval(set)).collect::<Vec<_>>();
+		if values.contains(&Some(false)) {
+		    Some(false)
+		} else {
+		    values.into_iter().collect::<Option<Vec<_>>>().map(|_| true)
+		}
+	    },
+	    When::Any(whens) => {
+		let values = whens.iter().map(|when| when.eval(set)).collect::<Vec<_>>();
+		if values.contains(&Some(true)) {
+		    Some(true)
+		} else {
+		    values.into_iter().collect::<Option<Vec<_>>>().map(|_| false)
+		}
+	    },
+	}
+    }
+
+    /// What's left to check once the flags `set` can tell are known. `all()` is left
+    /// where it's sure to hold, and `any()` where it's sure not to
+    pub fn bake(&self, set: &impl Fn(&str) -> Option<bool>) -> When {
+	match (self.eval(set), self) {
+	    (Some(true), _) => When::All(Vec::new()),
+	    (Some(false), _) => When::Any(Vec::new()),
+	    (None, When::Flag(flag)) => When::Flag(flag.clone()),
+	    (None, When::Not(when)) => When::Not(Box::new(when.bake(set))),
+	    // what's known can't change the answer, or it'd be known too
+	    (None, When::All(whens)) => When::All(whens.iter()
+						  .filter(|when| when.eval(set).is_none())
+						  .map(|when| when.bake(set)).collect()),
+	    (None, When::Any(whens)) => When::Any(whens.iter()
+						  .filter(|when| when.eval(set).is_none())
+						  .map(|when| when.bake(set)).collect()),
+	}
+    }
+}
+
+/// Written so that it parses back into the same expression
+impl std::fmt::Display for When {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	let list = |f: &mut std::fmt::Formatter, func: &str, whens: &[When]| {
+	    write!(f, "{}(", func)?;
+	    for (i, when) in whens.iter().enumerate() {
+		if i > 0 {
+		    write!(f, ", ")?;
+		}
+		write!(f, "{}", when)?;
+	    }
+	    write!(f, ")")
+	};
+	match self {
+	    When::Flag(flag) => write!(f, "{:?}", flag),
+	    When::Not(when) => write!(f, "not({})", when),
+	    When::All(whens) => list(f, "all", whens),
+	    When::Any(whens) => list(f, "any", whens),
+	}
+    }
+}
+
+/// The arguments of `#[override_flag]`:
+/// `(flag = x | when = ".." | field = x) [, value = "y" | min_occurrences = N]
+/// [, priority = N] [, invert = (true|false)]`. Flag names may be identifiers or string
+/// literals. A field's value is a pattern, such as `"Mode::Fast"`
+pub struct FlagArgs {
+    pub version: Version,
+    pub priority: u32,
+}
+
+impl Parse for FlagArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let mut flag = None;
+	let mut when = None;
+	let mut field = None;
+	let mut value = None;
+	let mut min_occurrences = None;
+	let mut priority = None;
+	let mut invert = None;
+	for arg in Punctuated::<Arg, Token![,]>::parse_terminated(input)? {
+	    match arg.name.to_string().as_str() {
+		"flag" => set(&mut flag, &arg, arg.value.flag_name()?)?,
+		"when" => set(&mut when, &arg, arg.value.string()?)?,
+		"field" => set(&mut field, &arg, arg.value.ident()?)?,
+		"value" => set(&mut value, &arg, arg.value.string()?)?,
+		"min_occurrences" => match arg.value.int()? {
+		    0 => return Err(syn::Error::new(arg.value.span(),
+						    "`min_occurrences` must be at least 1. The \
+						     #[default] is what runs without the flag")),
+		    min => set(&mut min_occurrences, &arg, min)?,
+		},
+		"priority" => set(&mut priority, &arg, arg.value.int()?)?,
+		"invert" => set(&mut invert, &arg, arg.value.boolean()?)?,
+		_ => return Err(syn::Error::new(arg.name.span(),
+						"Unexpected argument (expected flag, when, field, \
+						 value, min_occurrences, priority or invert)")),
+	    }
+	}
+	if let Some(field) = field {
+	    if flag.is_some() || when.is_some() {
+		return Err(syn::Error::new(field.span(),
+					   "Give one of `flag`, `when` or `field`, not several"));
+	    }
+	    if min_occurrences.is_some() {
+		return Err(syn::Error::new(field.span(), "A field can't be counted. Try \
+							  `value` with a pattern, such as \
+							  \"1..\""));
+	    }
+	    let field = field.to_string();
+	    let version = match value {
+		Some(value) if invert == Some(true) =>
+		    return Err(syn::Error::new(value.span(), "A field with a value can't be \
+							     inverted. Try a #[default] for \
+							     other values")),
+		Some(value) => {
+		    // checked here, so that it can be assumed well formed later
+		    value.parse::<syn::Pat>()
+			.map_err(|_| syn::Error::new(value.span(), "Expected a pattern"))?;
+		    Version::FieldValue{field, value: value.value()}
+		},
+		None => Version::Field{field, invert: invert.unwrap_or(false)},
+	    };
+	    return Ok(FlagArgs{version, priority: priority.unwrap_or(0)});
+	}
+	let value = value.map(|value| value.value());
+	let version = match (flag, when) {
+	    (Some(_), Some(when)) =>
+		return Err(syn::Error::new(when.span(), "Give either `flag` or `when`, not both")),
+	    (None, None) =>
+		return Err(syn::Error::new(Span::call_site(), "Missing mandatory argument 'flag'")),
+	    (None, Some(when)) => {
+		if value.is_some() || min_occurrences.is_some() || invert == Some(true) {
+		    return Err(syn::Error::new(when.span(), "`when` can't be given a value or be \
+							     inverted. Try `!` inside of it"));
+		}
+		When::parse(&when)?; // checked here, so that it can be assumed well formed later
+		Version::When(when.value())
+	    },
+	    (Some(_), None) if (value.is_some() || min_occurrences.is_some())
+		&& invert == Some(true) =>
+		return Err(syn::Error::new(Span::call_site(), "A flag with a value or occurrence \
+							       count can't be inverted. Try a \
+							       #[default] for other values")),
+	    (Some(flag), None) => match (value, min_occurrences) {
+		(Some(_), Some(_)) => return Err(syn::Error::new(
+		    Span::call_site(), "Give either `value` or `min_occurrences`, not both")),
+		(Some(value), None) => Version::Value{flag, value},
+		(None, Some(min)) => Version::Count{flag, min},
+		(None, None) => Version::Flag{flag, invert: invert.unwrap_or(false)},
+	    },
+	};
+	Ok(FlagArgs{version, priority: priority.unwrap_or(0)})
+    }
+}
+
+/// The arguments of `#[override_env]`:
+/// `var = "..." (, value = "..." | , set = true) [, priority = N]`
+pub struct EnvArgs {
+    pub version: Version,
+    pub priority: u32,
+}
+
+impl Parse for EnvArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let mut var = None;
+	let mut value = None;
+	let mut any_value = None;
+	let mut priority = None;
+	for arg in Punctuated::<Arg, Token![,]>::parse_terminated(input)? {
+	    match arg.name.to_string().as_str() {
+		"var" => set(&mut var, &arg, arg.value.string()?.value())?,
+		"value" => set(&mut value, &arg, arg.value.string()?.value())?,
+		"set" => match arg.value.boolean()? {
+		    true => set(&mut any_value, &arg, true)?,
+		    false => return Err(syn::Error::new(arg.value.span(),
+							"Only `set = true` is supported. Try a \
+							 #[default] for when it's unset")),
+		},
+		"priority" => set(&mut priority, &arg, arg.value.int()?)?,
+		_ => return Err(syn::Error::new(arg.name.span(),
+						"Unexpected argument (expected var, value, set \
+						 or priority)")),
+	    }
+	}
+	let var = var.ok_or_else(|| syn::Error::new(Span::call_site(),
+						    "Missing mandatory argument 'var'"))?;
+	if any_value.is_some() == value.is_some() {
+	    return Err(syn::Error::new(Span::call_site(),
+				       "Expected exactly one of `value = \"...\"` or `set = true`"));
+	}
+	Ok(EnvArgs{version: Version::Env{var, value}, priority: priority.unwrap_or(0)})
+    }
+}
+
+/// The arguments of `#[override_cpu]`: `feature = "..." [, priority = N]`
+pub struct CpuArgs {
+    pub feature: LitStr,
+    pub priority: u32,
+}
+
+impl CpuArgs {
+    pub fn version(&self) -> Version {
+	Version::Cpu(self.feature.value())
+    }
+}
+
+impl Parse for CpuArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let mut feature = None;
+	let mut priority = None;
+	for arg in Punctuated::<Arg, Token![,]>::parse_terminated(input)? {
+	    match arg.name.to_string().as_str() {
+		"feature" => set(&mut feature, &arg, arg.value.string()?)?,
+		"priority" => set(&mut priority, &arg, arg.value.int()?)?,
+		_ => return Err(syn::Error::new(arg.name.span(),
+						"Unexpected argument (expected feature or priority)")),
+	    }
+	}
+	let feature = feature.ok_or_else(|| syn::Error::new(Span::call_site(),
+							    "Missing mandatory argument 'feature'"))?;
+	Ok(CpuArgs{feature, priority: priority.unwrap_or(0)})
+    }
+}
+
+/// Name an impl block is keyed by: `Type` for inherent impls, and `Trait_for_Type`
+/// for trait impls, so that impls of different traits for one type don't collide
+pub fn impl_name(impl_block: &ItemImpl) -> syn::Result<String> {
+    let self_type = match impl_block.self_ty.as_ref() {
+	syn::Type::Path(path) => path_name(&path.path),
+	item => return Err(syn::Error::new(
+	    item.span(),
+	    "Could not get Path for impl (should never see this)")),
+    };
+    Ok(match &impl_block.trait_ {
+	None => self_type,
+	Some((_, trait_path, _)) => format!("{}_for_{}", path_name(trait_path), self_type),
+    })
+}
+
+/// `From<Vec<u8>>` => `From_Vec_u8`
+pub fn path_name(path: &syn::Path) -> String {
+    let segment = path.segments.last().unwrap(); // paths are never empty
+    let mut name = segment.ident.to_string();
+    if let PathArguments::AngleBracketed(args) = &segment.arguments {
+	for word in args.args.to_token_stream().to_string()
+	    .split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+		name.push('_');
+		name.push_str(word);
+	    }
+    }
+    name
+}
+
+/// Every name a `use` brings into scope. `parent` is what `self` refers to
+pub fn use_names(tree: &UseTree, parent: Option<&Ident>, names: &mut Vec<Ident>) {
+    match tree {
+	UseTree::Path(path) => use_names(&path.tree, Some(&path.ident), names),
+	UseTree::Name(name) if name.ident == "self" =>
+	    names.extend(parent.cloned()),
+	UseTree::Name(name) => names.push(name.ident.clone()),
+	UseTree::Rename(rename) if rename.rename == "_" => {},
+	UseTree::Rename(rename) => names.push(rename.rename.clone()),
+	UseTree::Glob(_) => {},
+	UseTree::Group(group) => for tree in &group.items {
+	    use_names(tree, parent, names);
+	},
+    }
+}
+
+/// The name `overrider_build` files the module prefixes of a file under, for `overrider`
+/// to find by the path rustc gives it
+pub fn file_id(file: &std::path::Path) -> String {
+    // FNV-1a, as the path may not be a valid env var name itself
+    let hash = file.to_string_lossy().bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+	(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Each argument may only be given once
+fn set<T>(slot: &mut Option<T>, arg: &Arg, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+	return Err(syn::Error::new(arg.name.span(), format!("`{}` is given twice", arg.name)));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// `name = value`
+struct Arg {
+    name: Ident,
+    value: Value,
+}
+
+enum Value {
+    Ident(Ident),
+    Str(LitStr),
+    Int(LitInt),
+    Bool(LitBool),
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+	let name = input.parse()?;
+	input.parse::<Token![=]>()?;
+	let lookahead = input.lookahead1();
+	let value = if lookahead.peek(LitStr) {
+	    Value::Str(input.parse()?)
+	} else if lookahead.peek(LitInt) {
+	    Value::Int(input.parse()?)
+	} else if lookahead.peek(LitBool) {
+	    Value::Bool(input.parse()?)
+	} else if lookahead.peek(Ident) {
+	    Value::Ident(input.parse()?)
+	} else {
+	    return Err(lookahead.error());
+	};
+	Ok(Arg{name, value})
+    }
+}
+
+impl Value {
+    fn span(&self) -> Span {
+	match self {
+	    Value::Ident(ident) => ident.span(),
+	    Value::Str(lit) => lit.span(),
+	    Value::Int(lit) => lit.span(),
+	    Value::Bool(lit) => lit.span,
+	}
+    }
+
+    /// `flag = dry_run` or `flag = "dry-run"`
+    fn flag_name(&self) -> syn::Result<String> {
+	let name = match self {
+	    Value::Ident(ident) => ident.to_string(),
+	    Value::Str(lit) => lit.value(),
+	    _ => return Err(syn::Error::new(self.span(),
+					    "Expected a flag name, as an identifier or string")),
+	};
+	if name.is_empty() {
+	    return Err(syn::Error::new(self.span(), "A flag name can't be empty"));
+	}
+	Ok(name)
+    }
+
+    fn ident(&self) -> syn::Result<Ident> {
+	match self {
+	    Value::Ident(ident) => Ok(ident.clone()),
+	    _ => Err(syn::Error::new(self.span(), "Expected a field name")),
+	}
+    }
+
+    fn string(&self) -> syn::Result<LitStr> {
+	match self {
+	    Value::Str(lit) => Ok(lit.clone()),
+	    _ => Err(syn::Error::new(self.span(), "Expected string literal")),
+	}
+    }
+
+    fn int<N: std::str::FromStr>(&self) -> syn::Result<N> where N::Err: std::fmt::Display {
+	match self {
+	    Value::Int(lit) => lit.base10_parse(),
+	    _ => Err(syn::Error::new(self.span(), "Expected positive integer literal")),
+	}
+    }
+
+    fn boolean(&self) -> syn::Result<bool> {
+	match self {
+	    Value::Bool(lit) => Ok(lit.value),
+	    _ => Err(syn::Error::new(self.span(), "Expected `true` or `false`")),
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(name: &str) -> When {
+	When::Flag(name.to_string())
+    }
+
+    /// The message `args` are rejected with
+    fn flag_error(args: &str) -> String {
+	match syn::parse_str::<FlagArgs>(args) {
+	    Ok(parsed) => panic!("`{}` parsed as {}", args, parsed.version),
+	    Err(error) => error.to_string(),
+	}
+    }
+
+    #[test]
+    fn flag_args() {
+	let parse = |args| syn::parse_str::<FlagArgs>(args)
+	    .map(|parsed| (parsed.version, parsed.priority))
+	    .unwrap_or_else(|error| panic!("{}: {}", args, error));
+	assert_eq!(parse("flag = a"), (Version::Flag{flag: "a".into(), invert: false}, 0));
+	assert_eq!(parse(r#"flag = "dry-run", invert = true, priority = 2"#),
+		   (Version::Flag{flag: "dry-run".into(), invert: true}, 2));
+	assert_eq!(parse(r#"flag = mode, value = "fast""#),
+		   (Version::Value{flag: "mode".into(), value: "fast".into()}, 0));
+	assert_eq!(parse("flag = v, min_occurrences = 2"),
+		   (Version::Count{flag: "v".into(), min: 2}, 0));
+	assert_eq!(parse(r#"when = "a && b", priority = 1"#),
+		   (Version::When("a && b".into()), 1));
+	assert_eq!(parse("field = fast, invert = true"),
+		   (Version::Field{field: "fast".into(), invert: true}, 0));
+	assert_eq!(parse(r#"field = mode, value = "Mode::Fast""#),
+		   (Version::FieldValue{field: "mode".into(), value: "Mode::Fast".into()}, 0));
+    }
+
+    #[test]
+    fn flag_args_errors() {
+	assert_eq!(flag_error("priority = 1"), "Missing mandatory argument 'flag'");
+	assert_eq!(flag_error("flag = a, flag = b"), "`flag` is given twice");
+	assert!(flag_error("flag = a, colour = 1").starts_with("Unexpected argument"));
+	assert_eq!(flag_error("flag = a, priority = x"), "Expected positive integer literal");
+	assert_eq!(flag_error("flag = a, invert = 1"), "Expected `true` or `false`");
+	assert!(flag_error("flag = v, min_occurrences = 0")
+		.starts_with("`min_occurrences` must be at least 1"));
+	assert_eq!(flag_error(r#"flag = a, when = "b""#), "Give either `flag` or `when`, not both");
+	assert!(flag_error(r#"when = "a", value = "b""#).starts_with("`when` can't be given"));
+	assert!(flag_error(r#"when = "a +""#).starts_with("unexpected end of input"));
+	assert!(flag_error(r#"when = "a + b""#).starts_with("Expected `&&` or `||`"));
+	assert!(flag_error(r#"flag = v, min_occurrences = 2, invert = true"#)
+		.starts_with("A flag with a value or occurrence count can't be inverted"));
+	assert_eq!(flag_error(r#"flag = m, value = "a", min_occurrences = 1"#),
+		   "Give either `value` or `min_occurrences`, not both");
+	assert_eq!(flag_error("field = a, flag = b"),
+		   "Give one of `flag`, `when` or `field`, not several");
+	assert!(flag_error("field = a, min_occurrences = 1").starts_with("A field can't be counted"));
+	assert_eq!(flag_error(r#"field = mode, value = "1 +""#), "Expected a pattern");
+	assert!(flag_error(r#"field = mode, value = "1..", invert = true"#)
+		.starts_with("A field with a value can't be inverted"));
+    }
+
+    #[test]
+    fn env_and_cpu_args() {
+	let env = syn::parse_str::<EnvArgs>(r#"var = "MODE", value = "fast", priority = 3"#)
+	    .unwrap();
+	assert_eq!(env.version, Version::Env{var: "MODE".into(), value: Some("fast".into())});
+	assert_eq!(env.priority, 3);
+	let env = |args| syn::parse_str::<EnvArgs>(args).err().map(|error| error.to_string());
+	assert_eq!(env(r#"var = "MODE", set = true"#), None);
+	assert_eq!(env(r#"value = "fast""#).unwrap(), "Missing mandatory argument 'var'");
+	assert_eq!(env(r#"var = "MODE""#).unwrap(),
+		   "Expected exactly one of `value = \"...\"` or `set = true`");
+	assert_eq!(env(r#"var = "MODE", value = "fast", set = true"#).unwrap(),
+		   "Expected exactly one of `value = \"...\"` or `set = true`");
+	assert!(env(r#"var = "MODE", set = false"#).unwrap().starts_with("Only `set = true`"));
+	let cpu = syn::parse_str::<CpuArgs>(r#"feature = "avx2""#).unwrap();
+	assert_eq!((cpu.version(), cpu.priority), (Version::Cpu("avx2".into()), 0));
+	assert_eq!(syn::parse_str::<CpuArgs>("priority = 1").err().unwrap().to_string(),
+		   "Missing mandatory argument 'feature'");
+    }
+
+    #[test]
+    fn when_precedence() {
+	let parse = |when| When::parse_str(when).unwrap();
+	assert_eq!(parse("a || b && c"),
+		   When::Any(vec![flag("a"), When::All(vec![flag("b"), flag("c")])]));
+	assert_eq!(parse("a && b || c"),
+		   When::Any(vec![When::All(vec![flag("a"), flag("b")]), flag("c")]));
+	assert_eq!(parse("!a && b"), When::All(vec![When::Not(Box::new(flag("a"))), flag("b")]));
+	assert_eq!(parse("!(a && b)"), When::Not(Box::new(When::All(vec![flag("a"), flag("b")]))));
+	assert_eq!(parse("(a || b) && c"),
+		   When::All(vec![When::Any(vec![flag("a"), flag("b")]), flag("c")]));
+	assert_eq!(parse(r#"any("dry-run", not(all(a)))"#),
+		   When::Any(vec![flag("dry-run"),
+				  When::Not(Box::new(When::All(vec![flag("a")])))]));
+	assert_eq!(parse("a && b && c").flags(), vec!["a", "b", "c"]);
+	assert_eq!(parse("a || !a").flags(), vec!["a"]);
+	assert!(When::parse_str("not(a, b)").is_err());
+	assert!(When::parse_str("a::b").is_err());
+    }
+
+    #[test]
+    fn when_eval() {
+	let only_a = |flag: &str| if flag == "a" {Some(true)} else {None};
+	let eval = |when| When::parse_str(when).unwrap().eval(&only_a);
+	assert_eq!(eval("a || b"), Some(true));
+	assert_eq!(eval("!a && b"), Some(false));
+	assert_eq!(eval("a && b"), None);
+	assert_eq!(eval("all()"), Some(true));
+	assert_eq!(eval("any()"), Some(false));
+	// written out and parsed back, the same expression is left
+	for when in &["a || b && !c", r#"not(any("dry-run", all()))"#, "any(a)"] {
+	    let when = When::parse_str(when).unwrap();
+	    assert_eq!(When::parse_str(&when.to_string()).unwrap(), when);
+	}
+    }
+
+    #[test]
+    fn item_names() {
+	let impl_name = |item: syn::ItemImpl| impl_name(&item).unwrap();
+	assert_eq!(impl_name(syn::parse_quote!(impl Dummy {})), "Dummy");
+	assert_eq!(impl_name(syn::parse_quote!(impl std::fmt::Display for Dummy {})),
+		   "Display_for_Dummy");
+	assert_eq!(impl_name(syn::parse_quote!(impl From<Vec<u8>> for Pair<u8> {})),
+		   "From_Vec_u8_for_Pair_u8");
+	assert!(super::impl_name(&syn::parse_quote!(impl Trait for [u8] {})).is_err());
+	let use_names = |item: syn::ItemUse| {
+	    let mut names = Vec::new();
+	    use_names(&item.tree, None, &mut names);
+	    names.iter().map(Ident::to_string).collect::<Vec<_>>()
+	};
+	assert_eq!(use_names(syn::parse_quote! {
+	    use std::io::{self, Read as R, Write as _, prelude::*};
+	}), vec!["io", "R"]);
+	assert_eq!(use_names(syn::parse_quote!(use std::*;)), Vec::<String>::new());
+	// a hex name any path fits in, and the same path always gets the same name
+	let id = file_id(std::path::Path::new("/src/main.rs"));
+	assert_eq!(id.len(), 16);
+	assert_eq!(id, file_id(std::path::Path::new("/src/main.rs")));
+	assert_ne!(id, file_id(std::path::Path::new("/src/lib.rs")));
+    }
+
+    #[test]
+    fn names_round_trip() {
+	let versions = vec![
+	    Version::Flag{flag: "a".into(), invert: false},
+	    Version::Flag{flag: "dry-run".into(), invert: true},
+	    Version::Value{flag: "mode".into(), value: "fast_or_safe".into()},
+	    Version::Value{flag: "mode".into(), value: "".into()},
+	    Version::Count{flag: "v".into(), min: 3},
+	    Version::When("a && !b".into()),
+	    Version::Cpu("avx2".into()),
+	    Version::Env{var: "MODE".into(), value: None},
+	    Version::Env{var: "MODE".into(), value: Some("ünïcode".into())},
+	    Version::Field{field: "fast".into(), invert: false},
+	    Version::Field{field: "fast".into(), invert: true},
+	    Version::FieldValue{field: "mode".into(), value: "Mode::Fast | Mode::Faster".into()},
+	];
+	for version in versions {
+	    let name = version.name();
+	    // names go into identifiers
+	    assert!(name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'), "{}", name);
+	    assert_eq!(Version::from_name(&name), Some(version));
+	}
+	assert_eq!(Version::from_name("x_61"), None);
+	assert_eq!(Version::from_name("f_6"), None);
+	assert_eq!(Version::from_name("n_61_many"), None);
+    }
+}
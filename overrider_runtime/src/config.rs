@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::FlagSource;
+
+/// Flags read from a configuration file, so versions can be picked per deployment
+/// without touching the command line
+///
+/// The file holds an `overrides` table, mapping each flag to what it's given:
+/// ```toml
+/// [overrides]
+/// mode = "fast"   # as with --mode=fast
+/// legacy = true   # as with --legacy. false leaves it unset
+/// v = 2           # as with -vv
+/// ```
+/// or the same in JSON, as in `{"overrides": {"mode": "fast"}}`. TOML is read with the
+/// `toml` feature, and JSON with the `json` feature.
+///
+/// Like any [`FlagSource`], it's registered with [`flag_source!`](crate::flag_source),
+/// on its own or after the command line, which then wins where both give a flag:
+/// ```ignore
+/// lazy_static::lazy_static! {
+///     static ref FLAGS: (clap::ArgMatches<'static>, overrider_runtime::ConfigFlags) = (
+///         clap::App::new("example").get_matches(),
+///         overrider_runtime::ConfigFlags::from_env("APP_OVERRIDES").unwrap(),
+///     );
+/// }
+///
+/// overrider_runtime::flag_source!(&*FLAGS);
+/// ```
+/// Flags pinned at build time with `OVERRIDER_BAKE_FLAGS` aren't read at all, so the file
+/// can't change them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigFlags {
+    flags: HashMap<String, Setting>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Setting {
+    Set(bool),
+    Value(String),
+    Count(u64),
+}
+
+impl ConfigFlags {
+    /// Reads the file at `path`, as JSON if it ends in `.json` and as TOML otherwise
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+	let path = path.as_ref();
+	let text = std::fs::read_to_string(path).map_err(ConfigError::Io)?;
+	match path.extension() {
+	    Some(extension) if extension == "json" => Self::from_json(&text),
+	    _ => Self::from_toml(&text),
+	}
+    }
+
+    /// Reads the file named by the environment variable `var`. With it unset, no flags
+    /// are given
+    pub fn from_env(var: &str) -> Result<Self, ConfigError> {
+	match std::env::var_os(var) {
+	    Some(path) => Self::load(path),
+	    None => Ok(Self::default()),
+	}
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+	let table = text.parse::<toml::Table>()
+	    .map_err(|error| ConfigError::Parse(error.to_string()))?;
+	let overrides = match table.get("overrides") {
+	    Some(toml::Value::Table(overrides)) => overrides,
+	    Some(_) => return Err(ConfigError::Invalid("`overrides` isn't a table".to_string())),
+	    None => return Ok(Self::default()),
+	};
+	let flags = overrides.iter().map(|(flag, value)| {
+	    let setting = match value {
+		toml::Value::Boolean(set) => Setting::Set(*set),
+		toml::Value::String(value) => Setting::Value(value.clone()),
+		toml::Value::Integer(count) if *count >= 0 => Setting::Count(*count as u64),
+		_ => return Err(ConfigError::Invalid(format!(
+		    "`{}` should be true, false, a string or a count", flag))),
+	    };
+	    Ok((flag.clone(), setting))
+	}).collect::<Result<_, _>>()?;
+	Ok(ConfigFlags{flags})
+    }
+
+    #[cfg(not(feature = "toml"))]
+    pub fn from_toml(_text: &str) -> Result<Self, ConfigError> {
+	Err(ConfigError::Unsupported("toml"))
+    }
+
+    #[cfg(feature = "json")]
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+	let json = serde_json::from_str::<serde_json::Value>(text)
+	    .map_err(|error| ConfigError::Parse(error.to_string()))?;
+	let overrides = match json.get("overrides") {
+	    Some(serde_json::Value::Object(overrides)) => overrides,
+	    Some(_) =>
+		return Err(ConfigError::Invalid("`overrides` isn't an object".to_string())),
+	    None => return Ok(Self::default()),
+	};
+	let flags = overrides.iter().map(|(flag, value)| {
+	    let setting = match value {
+		serde_json::Value::Bool(set) => Setting::Set(*set),
+		serde_json::Value::String(value) => Setting::Value(value.clone()),
+		serde_json::Value::Number(count) if count.is_u64() =>
+		    Setting::Count(count.as_u64().unwrap()),
+		_ => return Err(ConfigError::Invalid(format!(
+		    "`{}` should be true, false, a string or a count", flag))),
+	    };
+	    Ok((flag.clone(), setting))
+	}).collect::<Result<_, _>>()?;
+	Ok(ConfigFlags{flags})
+    }
+
+    #[cfg(not(feature = "json"))]
+    pub fn from_json(_text: &str) -> Result<Self, ConfigError> {
+	Err(ConfigError::Unsupported("json"))
+    }
+}
+
+impl FlagSource for ConfigFlags {
+    fn is_set(&self, flag: &str) -> bool {
+	match self.flags.get(flag) {
+	    Some(Setting::Set(set)) => *set,
+	    Some(Setting::Value(_)) => true,
+	    Some(Setting::Count(count)) => *count > 0,
+	    None => false,
+	}
+    }
+
+    fn value(&self, flag: &str) -> Option<&str> {
+	match self.flags.get(flag) {
+	    Some(Setting::Value(value)) => Some(value),
+	    _ => None,
+	}
+    }
+
+    fn occurrences(&self, flag: &str) -> u64 {
+	match self.flags.get(flag) {
+	    Some(Setting::Count(count)) => *count,
+	    _ => self.is_set(flag) as u64,
+	}
+    }
+}
+
+/// Why a configuration file couldn't be read
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    /// It isn't valid TOML or JSON
+    Parse(String),
+    /// It's valid, but doesn't hold flags as expected
+    Invalid(String),
+    /// The feature reading this format is off
+    Unsupported(&'static str),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	match self {
+	    ConfigError::Io(error) => write!(f, "Couldn't read flag overrides: {}", error),
+	    ConfigError::Parse(error) => write!(f, "Couldn't parse flag overrides: {}", error),
+	    ConfigError::Invalid(error) => write!(f, "Invalid flag overrides: {}", error),
+	    ConfigError::Unsupported(format) =>
+		write!(f, "Flag overrides in {} need overrider_runtime's `{}` feature",
+		       format, format),
+	}
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+	match self {
+	    ConfigError::Io(error) => Some(error),
+	    _ => None,
+	}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `text` to a file of its own, named `name`
+    fn write(name: &str, text: &str) -> std::path::PathBuf {
+	let dir = std::env::temp_dir().join(format!("overrider_config_{}", std::process::id()));
+	std::fs::create_dir_all(&dir).unwrap();
+	let path = dir.join(name);
+	std::fs::write(&path, text).unwrap();
+	path
+    }
+
+    fn check(flags: &ConfigFlags) {
+	assert!(flags.is_set("fast") && !flags.is_set("legacy") && !flags.is_set("missing"));
+	assert_eq!((flags.value("mode"), flags.value("fast"), flags.value("missing")),
+		   (Some("safe"), None, None));
+	assert!(flags.is_set("mode"));
+	assert_eq!((flags.occurrences("v"), flags.occurrences("fast"),
+		    flags.occurrences("legacy")), (2, 1, 0));
+	assert!(!flags.is_set("quiet")); // given no times
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn from_toml() {
+	let flags = ConfigFlags::from_toml("[overrides]\nfast = true\nlegacy = false\n\
+					    mode = \"safe\"\nv = 2\nquiet = 0\n").unwrap();
+	check(&flags);
+	assert_eq!(ConfigFlags::from_toml("[other]\nfast = true").unwrap(),
+		   ConfigFlags::default());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn from_json() {
+	let flags = ConfigFlags::from_json(r#"{"overrides": {"fast": true, "legacy": false,
+						"mode": "safe", "v": 2, "quiet": 0}}"#).unwrap();
+	check(&flags);
+	assert_eq!(ConfigFlags::from_json("{}").unwrap(), ConfigFlags::default());
+    }
+
+    #[cfg(all(feature = "toml", feature = "json"))]
+    #[test]
+    fn load() {
+	let toml = write("flags.toml", "[overrides]\nfast = true\n");
+	let json = write("flags.json", r#"{"overrides": {"mode": "safe"}}"#);
+	assert!(ConfigFlags::load(&toml).unwrap().is_set("fast"));
+	assert_eq!(ConfigFlags::load(&json).unwrap().value("mode"), Some("safe"));
+	std::env::set_var("OVERRIDER_TEST_CONFIG", &toml);
+	assert!(ConfigFlags::from_env("OVERRIDER_TEST_CONFIG").unwrap().is_set("fast"));
+	assert_eq!(ConfigFlags::from_env("OVERRIDER_TEST_CONFIG_UNSET").unwrap(),
+		   ConfigFlags::default());
+    }
+
+    #[test]
+    fn missing_file() {
+	let error = ConfigFlags::load(std::env::temp_dir().join("overrider_no_such_file.toml"))
+	    .unwrap_err();
+	assert!(matches!(error, ConfigError::Io(_)), "{}", error);
+	assert!(std::error::Error::source(&error).is_some());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_errors() {
+	let error = |text| ConfigFlags::from_toml(text).unwrap_err();
+	assert!(matches!(error("[overrides"), ConfigError::Parse(_)));
+	assert!(matches!(ConfigFlags::load(write("bad.toml", "fast = ")).unwrap_err(),
+			 ConfigError::Parse(_)));
+	assert!(matches!(error("overrides = 1"), ConfigError::Invalid(_)));
+	match error("[overrides]\nv = -1") {
+	    ConfigError::Invalid(message) =>
+		assert_eq!(message, "`v` should be true, false, a string or a count"),
+	    error => panic!("{}", error),
+	}
+	assert!(matches!(error("[overrides]\nfast = 1.5"), ConfigError::Invalid(_)));
+	assert!(matches!(error("[overrides]\nfast = [true]"), ConfigError::Invalid(_)));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_errors() {
+	let error = |text| ConfigFlags::from_json(text).unwrap_err();
+	assert!(matches!(error(r#"{"overrides": "#), ConfigError::Parse(_)));
+	assert!(matches!(ConfigFlags::load(write("bad.json", "{fast}")).unwrap_err(),
+			 ConfigError::Parse(_)));
+	assert!(matches!(error(r#"{"overrides": []}"#), ConfigError::Invalid(_)));
+	assert!(matches!(error(r#"{"overrides": {"v": -1}}"#), ConfigError::Invalid(_)));
+	assert!(matches!(error(r#"{"overrides": {"fast": null}}"#), ConfigError::Invalid(_)));
+    }
+
+    #[cfg(not(feature = "json"))]
+    #[test]
+    fn json_unsupported() {
+	let error = ConfigFlags::from_json("{}").unwrap_err();
+	assert!(matches!(error, ConfigError::Unsupported("json")));
+	assert_eq!(error.to_string(),
+		   "Flag overrides in json need overrider_runtime's `json` feature");
+    }
+
+    #[cfg(not(feature = "toml"))]
+    #[test]
+    fn toml_unsupported() {
+	assert!(matches!(ConfigFlags::from_toml("").unwrap_err(),
+			 ConfigError::Unsupported("toml")));
+    }
+}
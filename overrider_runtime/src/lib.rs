@@ -0,0 +1,285 @@
+//! `overrider_runtime` is the sister crate to `overrider`, providing what code generated
+//! by `#[override_flag]` needs at runtime.
+//!
+//! ## Flag sources
+//! Flagged items check whether their flag is set with a [`FlagSource`]. Any type can be
+//! one, and there are implementations ready for closures, and for `clap`'s `ArgMatches`
+//! behind the `clap2` and `clap4` features. Each crate using `#[override_flag]` registers
+//! its source once, at the crate root, with [`flag_source!`]:
+//! ```ignore
+//! lazy_static::lazy_static! {
+//!     static ref CLAP_FLAGS: clap::ArgMatches<'static> = {
+//!         clap::App::new("example")
+//!             .arg(clap::Arg::with_name("change").long("change"))
+//!             .get_matches()
+//!     };
+//! }
+//!
+//! overrider_runtime::flag_source!(&*CLAP_FLAGS);
+//! ```
+//!
+//! A command line parsed into a struct, as with `clap`'s `#[derive(Parser)]`, can be read
+//! field by field instead, once registered with [`flag_parser!`].
+//!
+//! ## Command line
+//! The flags themselves needn't be declared by hand: `overrider_build` lists every flag
+//! a crate reads, which [`Flag`] turns into `clap` arguments.
+//! Where they are declared by hand, [`check_flags`] makes sure none read are missing.
+//!
+//! ## Configuration files
+//! Flags can also come from a TOML or JSON file with `ConfigFlags`, behind the `toml` and
+//! `json` features, to switch versions per deployment. Pairing it with the command line,
+//! as in `(matches, config)`, reads both, with the command line first.
+
+#[cfg(any(feature = "toml", feature = "json"))]
+mod config;
+
+#[cfg(any(feature = "toml", feature = "json"))]
+pub use config::{ConfigError, ConfigFlags};
+
+/// Somewhere flags can be read from, such as parsed command line arguments
+pub trait FlagSource: Sync {
+    /// Whether `flag` was given. Flags that aren't known of are not set
+    fn is_set(&self, flag: &str) -> bool;
+
+    /// The value `flag` was given, as in `--mode=fast`. Sources without values have none
+    fn value(&self, _flag: &str) -> Option<&str> {
+	None
+    }
+
+    /// How many times `flag` was given, as in `-vvv`. By default, once if it's set
+    fn occurrences(&self, flag: &str) -> u64 {
+	self.is_set(flag) as u64
+    }
+}
+
+/// For a quick source, such as environment variables:
+/// `|flag: &str| std::env::var_os(flag).is_some()`
+impl<F: Fn(&str) -> bool + Sync> FlagSource for F {
+    fn is_set(&self, flag: &str) -> bool {
+	self(flag)
+    }
+}
+
+/// Both sources, with the first winning where both give a value
+impl<A: FlagSource, B: FlagSource> FlagSource for (A, B) {
+    fn is_set(&self, flag: &str) -> bool {
+	self.0.is_set(flag) || self.1.is_set(flag)
+    }
+
+    fn value(&self, flag: &str) -> Option<&str> {
+	self.0.value(flag).or_else(|| self.1.value(flag))
+    }
+
+    fn occurrences(&self, flag: &str) -> u64 {
+	match self.0.occurrences(flag) {
+	    0 => self.1.occurrences(flag),
+	    occurrences => occurrences,
+	}
+    }
+}
+
+#[cfg(feature = "clap2")]
+impl FlagSource for clap2::ArgMatches<'_> {
+    fn is_set(&self, flag: &str) -> bool {
+	self.occurrences_of(flag) > 0
+    }
+
+    fn value(&self, flag: &str) -> Option<&str> {
+	self.value_of(flag)
+    }
+
+    fn occurrences(&self, flag: &str) -> u64 {
+	self.occurrences_of(flag)
+    }
+}
+
+#[cfg(feature = "clap4")]
+impl FlagSource for clap4::ArgMatches {
+    fn is_set(&self, flag: &str) -> bool {
+	// clap panics in debug builds when asked where an undeclared flag came from
+	if !self.try_contains_id(flag).unwrap_or(false) {
+	    return false;
+	}
+	match self.try_get_one::<bool>(flag) {
+	    Ok(Some(set)) => *set, // `ArgAction::SetTrue` and friends always have a value
+	    _ => matches!(self.value_source(flag),
+			  Some(clap4::parser::ValueSource::CommandLine)
+			  | Some(clap4::parser::ValueSource::EnvVariable)),
+	}
+    }
+
+    fn value(&self, flag: &str) -> Option<&str> {
+	match self.try_get_one::<String>(flag) {
+	    Ok(Some(value)) => Some(value),
+	    _ => None,
+	}
+    }
+
+    fn occurrences(&self, flag: &str) -> u64 {
+	if !self.try_contains_id(flag).unwrap_or(false) {
+	    return 0;
+	}
+	match self.try_get_one::<u8>(flag) {
+	    Ok(Some(count)) => *count as u64, // `ArgAction::Count`
+	    _ => self.is_set(flag) as u64,
+	}
+    }
+}
+
+/// A flag read by `#[override_flag]`
+///
+/// `overrider_build` lists every flag a crate reads as `OVERRIDER_FLAGS`, in
+/// `OUT_DIR/overrider_flags.rs`, with help taken from the doc comments of flagged items.
+/// Those can be turned into arguments for `clap`, alongside any others:
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/overrider_flags.rs"));
+///
+/// let matches = clap::App::new("example")
+///     .args(&OVERRIDER_FLAGS.iter().map(|flag| flag.clap2()).collect::<Vec<_>>())
+///     .get_matches();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flag {
+    pub name: &'static str,
+    pub help: &'static str,
+    /// Read with `value = "..."`
+    pub takes_value: bool,
+    /// Read with `min_occurrences` above 1
+    pub multiple: bool,
+    /// Where it's read, as `src/main.rs:12`
+    pub locations: &'static [&'static str],
+}
+
+impl Flag {
+    /// One letter names are short (`-v`), and others long (`--verbose`)
+    #[cfg(feature = "clap2")]
+    pub fn clap2(&self) -> clap2::Arg<'static, 'static> {
+	let arg = clap2::Arg::with_name(self.name)
+	    .help(self.help)
+	    .takes_value(self.takes_value)
+	    .multiple(self.multiple);
+	if self.name.chars().count() == 1 {
+	    arg.short(self.name)
+	} else {
+	    arg.long(self.name)
+	}
+    }
+
+    /// One letter names are short (`-v`), and others long (`--verbose`)
+    #[cfg(feature = "clap4")]
+    pub fn clap4(&self) -> clap4::Arg {
+	let arg = clap4::Arg::new(self.name)
+	    .help(self.help)
+	    .action(if self.takes_value {
+		clap4::ArgAction::Set
+	    } else if self.multiple {
+		clap4::ArgAction::Count
+	    } else {
+		clap4::ArgAction::SetTrue
+	    });
+	let mut chars = self.name.chars();
+	match (chars.next(), chars.next()) {
+	    (Some(short), None) => arg.short(short),
+	    _ => arg.long(self.name),
+	}
+    }
+}
+
+/// Flags read by `#[override_flag]` that the command line parser doesn't declare, and so
+/// can never be set. Most likely they're misspelled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownFlags(pub Vec<Flag>);
+
+impl std::fmt::Display for UnknownFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+	writeln!(f, "Flags read by #[override_flag] that are never declared:")?;
+	for flag in &self.0 {
+	    writeln!(f, "    `{}`, at {}", flag.name, flag.locations.join(", "))?;
+	}
+	Ok(())
+    }
+}
+
+impl std::error::Error for UnknownFlags {}
+
+/// Checks every flag in `flags` (normally `OVERRIDER_FLAGS`, see [`Flag`]) is one of the
+/// `declared` arguments, for a test or early in `main`:
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/overrider_flags.rs"));
+///
+/// #[test]
+/// fn flags_are_declared() {
+///     overrider_runtime::check_flags(OVERRIDER_FLAGS, ["disable", "verbose"]).unwrap();
+/// }
+/// ```
+pub fn check_flags<'a>(flags: &[Flag], declared: impl IntoIterator<Item = &'a str>)
+		       -> Result<(), UnknownFlags> {
+    let declared = declared.into_iter().collect::<Vec<_>>();
+    let unknown = flags.iter().filter(|flag| !declared.contains(&flag.name)).copied()
+	.collect::<Vec<_>>();
+    if unknown.is_empty() {
+	Ok(())
+    } else {
+	Err(UnknownFlags(unknown))
+    }
+}
+
+/// [`check_flags`] against every argument of a `clap` 4 `Command`
+#[cfg(feature = "clap4")]
+pub fn check_clap4(flags: &[Flag], command: &clap4::Command) -> Result<(), UnknownFlags> {
+    check_flags(flags, command.get_arguments().map(|arg| arg.get_id().as_str()))
+}
+
+/// Registers where flags are read from, for every `#[override_flag]` in the crate
+///
+/// This must be used once, at the root of the crate (`main.rs` or `lib.rs`). It takes a
+/// `&'static` reference to anything implementing [`FlagSource`]:
+/// ```ignore
+/// static FLAGS: MyFlags = MyFlags::new();
+/// overrider_runtime::flag_source!(&FLAGS);
+/// ```
+#[macro_export]
+macro_rules! flag_source {
+    ($source:expr) => {
+	#[doc(hidden)]
+	#[allow(dead_code)]
+	pub(crate) fn __overrider_flag_source() -> &'static dyn $crate::FlagSource {
+	    $source
+	}
+    };
+}
+
+/// Registers the parser struct read by `#[override_flag(field = ..)]`, for every flagged
+/// item in the crate, such as one made with `clap`'s `#[derive(Parser)]`
+///
+/// Like [`flag_source!`], this must be used once, at the root of the crate. It takes the
+/// type of the struct and a `&'static` reference to it:
+/// ```ignore
+/// #[derive(clap::Parser)]
+/// struct Cli {
+///     #[arg(long)]
+///     verbose: bool,
+///     #[arg(long, value_enum, default_value = "safe")]
+///     mode: Mode,
+/// }
+///
+/// lazy_static::lazy_static! {
+///     static ref CLI: Cli = clap::Parser::parse();
+/// }
+///
+/// overrider_runtime::flag_parser!(Cli, &*CLI);
+/// ```
+/// A `bool` field picks a version when it's true, and any field can be matched against
+/// a pattern with `value`, as in `#[override_flag(field = mode, value = "Mode::Fast")]`.
+/// The pattern is checked where the flagged item is, so any paths in it must resolve there
+#[macro_export]
+macro_rules! flag_parser {
+    ($parser:ty, $source:expr) => {
+	#[doc(hidden)]
+	#[allow(dead_code)]
+	pub(crate) fn __overrider_flag_parser() -> &'static $parser {
+	    $source
+	}
+    };
+}